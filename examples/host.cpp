@@ -5,6 +5,9 @@
 
 #include <stdio.h>
 #include <cassert>
+#include <cstdlib>
+#include <cstring>
+#include <unistd.h>
 #include "rlbox.hpp"
 #include "rlbox_noop_sandbox.hpp"
 
@@ -84,6 +87,20 @@ int trivial_array_read_2d() {
  return 0;
 }
 
+// safe as a whole, but the column index overflows into the next row: strict bounds should fail
+int array_read_2d_inner_oob() {
+ int host_array[2][3] = { {1, 4, 2}, {3, 6, 8} };
+ host_array[0][5];
+ return 0;
+}
+
+// strict bounds should fail when column >= 3, even though the read may stay inside the array
+int array_read_2d_from_arg(uint32_t column) {
+ int host_array[2][3] = { {1, 4, 2}, {3, 6, 8} };
+ host_array[0][column];
+ return 0;
+}
+
 int basic_oob_read() {
   int32_t host_array[4] = { 100, 200, 300, 400 };
   host_array[4];
@@ -121,6 +138,12 @@ int trivial_struct_read() {
   return host_struct.a;
 }
 
+// should be safe: each field is read through its own pointer
+int struct_read_two_fields() {
+  SimpleStruct host_struct = { 100, 200 };
+  return host_struct.a + host_struct.b;
+}
+
 // should be safe
 int trivial_struct_read_nested() {
   ComplexStruct host_struct = { {100, 200}, {300, 400} };
@@ -158,6 +181,54 @@ int basic_div_by_zero_guarded(int denominator) {
   return 3 / denominator; // return value so that the compiler doesn't optimize the read away
 }
 
+// should fail: checking the guarded division must not hide the unguarded one after it
+int div_by_zero_after_guarded(int denominator, int other) {
+  if (denominator == 0) {
+    return 0;
+  }
+  int quotient = 3 / denominator;
+  return quotient / other;
+}
+
+// should fail twice: exhaustive runs should report both the OOB read and the division
+int two_independent_bugs(uint32_t index, int denominator) {
+  int32_t host_array[4] = { 100, 200, 300, 400 };
+  int32_t value = host_array[index];
+  return value / denominator;
+}
+
+// should be safe
+int heap_read_in_bounds() {
+  int32_t* buffer = (int32_t*)malloc(4 * sizeof(int32_t));
+  buffer[3] = 1337;
+  int32_t value = buffer[3];
+  free(buffer);
+  return value;
+}
+
+// should fail: writes one element past the end of a malloc'd buffer
+int heap_oob_write() {
+  int32_t* buffer = (int32_t*)malloc(4 * sizeof(int32_t));
+  buffer[4] = 1337;
+  free(buffer);
+  return 0;
+}
+
+// should fail: copies more than the destination can hold
+int memcpy_oob(uint32_t len) {
+  char src[8] = { 0 };
+  char dst[4];
+  memcpy(dst, src, len);
+  return dst[0];
+}
+
+// should not be flagged by mem_safety: nothing ties the parameter to `local`, so there is no
+// allocation to check the read against
+int param_pointer_read(int32_t* param) {
+  int32_t local[2] = { 1, 2 };
+  return local[1] + param[4];
+}
+
 
 // Data transfer test cases (no sandbox control flow)
 
@@ -274,9 +345,9 @@ int sandbox_array_index_checked() {
   // Create host array (not in sandbox)
   std::array<int32_t, 4> host_array = { 100, 200, 300, 400 };
 
-  // Read the first int from sandbox array using unsafe_unverified
+  // Read the first int from sandbox array, checking that it is a valid index
   auto index =(*sandbox_array)[0].copy_and_verify([&](int32_t index) {
-    if (index < 4){
+    if (index >= 0 && index < 4){
     return index;
     }
     else {
@@ -285,11 +356,721 @@ int sandbox_array_index_checked() {
   });
 
   // Use the lifted value to index into host array
-  // Note: In real applications, you should validate the index is in bounds
   host_array[index];
 
   // Cleanup
   sandbox.destroy_sandbox();
   
   return 0;
-}
\ No newline at end of file
+}
+
+// should fail: the verifier forgets that the index is signed, so a negative one gets through
+int sandbox_array_index_checked_signed() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_array = sandbox.malloc_in_sandbox<int32_t[4]>();
+  sandbox.invoke_sandbox_function(hello);
+
+  int32_t host_array[4] = { 100, 200, 300, 400 };
+  auto index = (*sandbox_array)[0].copy_and_verify([&](int32_t index) {
+    if (index < 4) {
+      return index;
+    }
+    return 3;
+  });
+  host_array[index];
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+struct FieldArrayStruct {
+  int32_t arr[4];
+  int32_t secret;
+};
+
+// should be safe
+int struct_field_array_read() {
+  FieldArrayStruct host_struct = { {1, 2, 3, 4}, 5 };
+  return host_struct.arr[3];
+}
+
+// should be safe: one past the end of the field is a fine address to stop at, as long as
+// nothing reads through it
+int struct_field_array_end() {
+  FieldArrayStruct host_struct = { {1, 2, 3, 4}, 5 };
+  int32_t sum = 0;
+  for (int32_t* p = host_struct.arr; p != &host_struct.arr[4]; p++) {
+    sum += *p;
+  }
+  return sum;
+}
+
+// index 4 reads the sibling field: strict bounds should fail
+int struct_field_array_overflow(uint32_t index) {
+  FieldArrayStruct host_struct = { {1, 2, 3, 4}, 5 };
+  return host_struct.arr[index];
+}
+
+// should fail: the guest can overwrite its memory during the call, so the host's
+// earlier write no longer tells us anything about the index
+int sandbox_index_after_invoke() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_array = sandbox.malloc_in_sandbox<int32_t[4]>();
+  (*sandbox_array)[0] = 2;
+
+  sandbox.invoke_sandbox_function(hello);
+
+  std::array<int32_t, 4> host_array = { 100, 200, 300, 400 };
+  auto index = (*sandbox_array)[0].UNSAFE_unverified();
+  host_array[index];
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: a compromised guest can return anything from add
+int sandbox_return_as_index() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  int32_t host_array[4] = { 100, 200, 300, 400 };
+  auto sum = sandbox.invoke_sandbox_function(add, 1, 2).UNSAFE_unverified();
+  host_array[sum];
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should be safe: the host writes the index again after the sandbox has run
+int sandbox_index_rewritten_after_invoke() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_array = sandbox.malloc_in_sandbox<int32_t[4]>();
+  (*sandbox_array)[0] = 10;
+
+  sandbox.invoke_sandbox_function(hello);
+  (*sandbox_array)[0] = 2;
+
+  std::array<int32_t, 4> host_array = { 100, 200, 300, 400 };
+  auto index = (*sandbox_array)[0].UNSAFE_unverified();
+  host_array[index];
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// safe if the guest is single-threaded, but another guest thread can change the index
+// between the check and the use
+int sandbox_index_read_twice() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_array = sandbox.malloc_in_sandbox<uint32_t[4]>();
+  sandbox.invoke_sandbox_function(hello);
+
+  int32_t host_array[4] = { 100, 200, 300, 400 };
+  if ((*sandbox_array)[0].UNSAFE_unverified() < 4) {
+    host_array[(*sandbox_array)[0].UNSAFE_unverified()];
+  }
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: the size is checked on one read of sandbox memory and used from another,
+// so the guest can change it in between
+int sandbox_double_fetch_copy() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_len = sandbox.malloc_in_sandbox<uint32_t>();
+  sandbox.invoke_sandbox_function(hello);
+
+  char host_buffer[16] = { 0 };
+  char source[16] = { 0 };
+  if ((*sandbox_len).UNSAFE_unverified() <= sizeof(host_buffer)) {
+    memcpy(host_buffer, source, (*sandbox_len).UNSAFE_unverified());
+  }
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: the index comes from the sandbox, by way of arithmetic and a host local
+int sandbox_index_through_host_memory() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_array = sandbox.malloc_in_sandbox<uint32_t[4]>();
+  sandbox.invoke_sandbox_function(hello);
+
+  int32_t host_array[4] = { 100, 200, 300, 400 };
+  uint32_t saved[2] = { 0, 0 };
+  saved[1] = (*sandbox_array)[0].UNSAFE_unverified() + 1;
+  host_array[saved[1] & 7];
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// never reads out of bounds, but how long the loop runs is up to the sandbox: the tainted use
+// checker should warn
+int sandbox_loop_bound() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_len = sandbox.malloc_in_sandbox<uint32_t>();
+  sandbox.invoke_sandbox_function(hello);
+
+  int32_t host_array[4] = { 100, 200, 300, 400 };
+  int32_t sum = 0;
+  uint32_t len = (*sandbox_len).UNSAFE_unverified();
+  for (uint32_t i = 0; i < len; i++) {
+    sum += host_array[i % 4];
+  }
+
+  sandbox.destroy_sandbox();
+  return sum;
+}
+
+// should be safe: the verifier bounds the string before the host copies it
+int sandbox_string_checked() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  tainted_guest<char*> sandbox_str = sandbox.malloc_in_sandbox<char>(16);
+  sandbox.invoke_sandbox_function(hello);
+
+  char host_buffer[8];
+  auto checked = sandbox_str.copy_and_verify_string([](unique_ptr<char[]> val) {
+    release_assert(val != nullptr && strlen(val.get()) < sizeof(host_buffer), "string too long\n");
+    return val;
+  });
+  memcpy(host_buffer, checked.get(), strlen(checked.get()) + 1);
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: the verifier never looks at the length, so the copy can overflow
+int sandbox_string_unbounded() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  tainted_guest<char*> sandbox_str = sandbox.malloc_in_sandbox<char>(16);
+  sandbox.invoke_sandbox_function(hello);
+
+  char host_buffer[8];
+  auto checked = sandbox_str.copy_and_verify_string([](unique_ptr<char[]> val) {
+    release_assert(val != nullptr, "val is null\n");
+    return val;
+  });
+  memcpy(host_buffer, checked.get(), strlen(checked.get()) + 1);
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: the verifier bounds the length, but not by the 16 bytes the string has, so it
+// may run on into whatever the guest put after it
+int sandbox_string_unterminated() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  tainted_guest<char*> sandbox_str = sandbox.malloc_in_sandbox<char>(16);
+  sandbox.invoke_sandbox_function(hello);
+
+  char host_buffer[64];
+  auto checked = sandbox_str.copy_and_verify_string([](unique_ptr<char[]> val) {
+    release_assert(val != nullptr && strlen(val.get()) < sizeof(host_buffer), "string too long\n");
+    return val;
+  });
+  memcpy(host_buffer, checked.get(), strlen(checked.get()) + 1);
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: the verifier bounds the length, but far past anything the host would take
+int sandbox_string_over_limit() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_str = sandbox.malloc_in_sandbox<char>(1 << 20);
+  sandbox.invoke_sandbox_function(hello);
+
+  auto checked = sandbox_str.copy_and_verify_string([](unique_ptr<char[]> val) {
+    release_assert(val != nullptr && strlen(val.get()) < (1 << 20), "string too long\n");
+    return val;
+  });
+  printf("%s\n", checked.get());
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: the host reads a string straight out of sandbox memory, where the guest decides
+// how long it is and whether it ends at all
+int sandbox_string_unverified() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  tainted_guest<char*> sandbox_str = sandbox.malloc_in_sandbox<char>(16);
+  sandbox.invoke_sandbox_function(hello);
+
+  char* str = sandbox_str.UNSAFE_unverified();
+  size_t len = strlen(str);
+
+  sandbox.destroy_sandbox();
+  return len;
+}
+
+// should be safe: the copy is only read within the count it was made with
+int sandbox_range_in_bounds() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  tainted_guest<int32_t*> sandbox_buffer = sandbox.malloc_in_sandbox<int32_t>(4);
+  sandbox.invoke_sandbox_function(hello);
+
+  auto copy = sandbox_buffer.copy_and_verify_range([](unique_ptr<int32_t[]> val) {
+    return val;
+  }, 4);
+  int32_t sum = 0;
+  for (int i = 0; i < 4; i++) {
+    sum += copy[i];
+  }
+
+  sandbox.destroy_sandbox();
+  return sum;
+}
+
+// should fail: reads one element past the end of the copy
+int sandbox_range_overread() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  tainted_guest<int32_t*> sandbox_buffer = sandbox.malloc_in_sandbox<int32_t>(4);
+  sandbox.invoke_sandbox_function(hello);
+
+  auto copy = sandbox_buffer.copy_and_verify_range([](unique_ptr<int32_t[]> val) {
+    return val;
+  }, 4);
+  int32_t last = copy[4];
+
+  sandbox.destroy_sandbox();
+  return last;
+}
+
+// should be safe: the guest picks how many elements to copy, but the verifier keeps that to
+// what the host has room for
+int sandbox_range_sandbox_count() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_count = sandbox.malloc_in_sandbox<uint32_t>();
+  tainted_guest<int32_t*> sandbox_buffer = sandbox.malloc_in_sandbox<int32_t>(64);
+  sandbox.invoke_sandbox_function(hello);
+
+  uint32_t count = (*sandbox_count).copy_and_verify([](uint32_t val) {
+    release_assert(val <= 16, "count too large\n");
+    return val;
+  });
+  auto copy = sandbox_buffer.copy_and_verify_range([](unique_ptr<int32_t[]> val) {
+    return val;
+  }, count);
+  int32_t host_array[16];
+  memcpy(host_array, copy.get(), count * sizeof(int32_t));
+
+  sandbox.destroy_sandbox();
+  return count;
+}
+
+// should fail: the guest picks how many elements to copy, and nothing stops that from being more
+// than the host has room for
+int sandbox_range_sandbox_count_unverified() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_count = sandbox.malloc_in_sandbox<uint32_t>();
+  tainted_guest<int32_t*> sandbox_buffer = sandbox.malloc_in_sandbox<int32_t>(64);
+  sandbox.invoke_sandbox_function(hello);
+
+  uint32_t count = (*sandbox_count).UNSAFE_unverified();
+  auto copy = sandbox_buffer.copy_and_verify_range([](unique_ptr<int32_t[]> val) {
+    return val;
+  }, count);
+  int32_t host_array[16];
+  memcpy(host_array, copy.get(), count * sizeof(int32_t));
+
+  sandbox.destroy_sandbox();
+  return count;
+}
+
+// should be safe: only the verified bytes of the buffer are written
+int sandbox_buffer_address_in_bounds() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  tainted_guest<char*> sandbox_buffer = sandbox.malloc_in_sandbox<char>(16);
+  sandbox.invoke_sandbox_function(hello);
+
+  uintptr_t address = sandbox_buffer.copy_and_verify_buffer_address([](uintptr_t val) {
+    return val;
+  }, 8);
+  char* buffer = reinterpret_cast<char*>(address);
+  buffer[7] = 0;
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: the write lands in sandbox memory, but outside the 8 bytes that were verified
+int sandbox_buffer_address_overflow() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  tainted_guest<char*> sandbox_buffer = sandbox.malloc_in_sandbox<char>(16);
+  sandbox.invoke_sandbox_function(hello);
+
+  uintptr_t address = sandbox_buffer.copy_and_verify_buffer_address([](uintptr_t val) {
+    return val;
+  }, 8);
+  char* buffer = reinterpret_cast<char*>(address);
+  buffer[8] = 0;
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: a 64-bit index from the sandbox can be anything
+int sandbox_index_64bit() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_array = sandbox.malloc_in_sandbox<int64_t[2]>();
+  sandbox.invoke_sandbox_function(hello);
+
+  int32_t host_array[4] = { 100, 200, 300, 400 };
+  int64_t index = (*sandbox_array)[1].UNSAFE_unverified();
+  host_array[index];
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: the whole array comes back by value, through an sret pointer as it is too large
+// for registers, and any of its elements can be anything
+int sandbox_array_by_value() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_array = sandbox.malloc_in_sandbox<int64_t[4]>();
+  sandbox.invoke_sandbox_function(hello);
+
+  int32_t host_array[4] = { 100, 200, 300, 400 };
+  std::array<int64_t, 4> values = (*sandbox_array).UNSAFE_unverified();
+  host_array[values[3]];
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should be safe: a flag from the sandbox can only pick one of two elements
+int sandbox_bool_index() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_flag = sandbox.malloc_in_sandbox<bool>();
+  sandbox.invoke_sandbox_function(hello);
+
+  int32_t host_array[2] = { 100, 200 };
+  bool flag = (*sandbox_flag).UNSAFE_unverified();
+  host_array[flag];
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should be safe: the host copies into exactly the range it vouched for
+int sandbox_pointer_within_grant() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  tainted_guest<char*> sandbox_buffer = sandbox.malloc_in_sandbox<char>(8);
+  strncpy(sandbox_buffer.unverified_safe_pointer_because(8, "writing to region"), "hello", 8);
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: the host vouches for 4 bytes and then copies 8
+int sandbox_pointer_beyond_grant() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  tainted_guest<char*> sandbox_buffer = sandbox.malloc_in_sandbox<char>(8);
+  strncpy(sandbox_buffer.unverified_safe_pointer_because(4, "writing to region"), "hello", 8);
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: the sandbox hands the host a pointer, which it writes through without checking
+// that it points into sandbox memory
+int sandbox_pointer_escape() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_ptr = sandbox.malloc_in_sandbox<char*>();
+  sandbox.invoke_sandbox_function(hello);
+
+  char* ptr = (*sandbox_ptr).UNSAFE_unverified();
+  ptr[0] = 'x';
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: the sandbox is used before it is created
+int sandbox_use_before_create() {
+  rlbox_sandbox_guest sandbox;
+  auto sandbox_array = sandbox.malloc_in_sandbox<int32_t[4]>();
+  sandbox.create_sandbox();
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: the host reads tainted data out of a sandbox it already destroyed
+int sandbox_use_after_destroy() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_array = sandbox.malloc_in_sandbox<int32_t[4]>();
+  sandbox.destroy_sandbox();
+
+  return (*sandbox_array)[0].UNSAFE_unverified();
+}
+
+// should fail: the sandbox is destroyed twice
+int sandbox_double_destroy() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  sandbox.destroy_sandbox();
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: the host reads a sandbox block after giving it back
+int sandbox_use_after_free() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_array = sandbox.malloc_in_sandbox<int32_t[4]>();
+  sandbox.free_in_sandbox(sandbox_array);
+  int32_t value = (*sandbox_array)[0].UNSAFE_unverified();
+
+  sandbox.destroy_sandbox();
+  return value;
+}
+
+// should fail: the same block is freed twice
+int sandbox_double_free() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  tainted_guest<char*> sandbox_buffer = sandbox.malloc_in_sandbox<char>(8);
+  sandbox.free_in_sandbox(sandbox_buffer);
+  sandbox.free_in_sandbox(sandbox_buffer);
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should fail: the guest hands over a pointer that needn't have come from malloc_in_sandbox,
+// and the host frees it
+int sandbox_free_guest_pointer() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_ptr = sandbox.malloc_in_sandbox<char*>();
+  sandbox.invoke_sandbox_function(hello);
+
+  tainted_guest<char*> guest_buffer = *sandbox_ptr;
+  sandbox.free_in_sandbox(guest_buffer);
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// should be safe: each block is freed once, and not used after
+int sandbox_free_once() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  tainted_guest<char*> sandbox_buffer = sandbox.malloc_in_sandbox<char>(8);
+  auto sandbox_array = sandbox.malloc_in_sandbox<int32_t[4]>();
+  sandbox.free_in_sandbox(sandbox_buffer);
+  sandbox.free_in_sandbox(sandbox_array);
+
+  sandbox.destroy_sandbox();
+  return 0;
+}
+
+// A sandbox that outlives the functions using it, and somewhere to keep a block of its memory
+rlbox_sandbox_guest long_lived_sandbox;
+char* kept_buffer;
+
+// should warn: one of the blocks is never freed, and the sandbox lives on
+int sandbox_memory_leak() {
+  long_lived_sandbox.create_sandbox();
+
+  tainted_guest<char*> sandbox_buffer = long_lived_sandbox.malloc_in_sandbox<char>(8);
+  auto sandbox_array = long_lived_sandbox.malloc_in_sandbox<int32_t[4]>();
+  long_lived_sandbox.free_in_sandbox(sandbox_buffer);
+
+  return 0;
+}
+
+// should not warn: the block is kept where it can still be freed later
+int sandbox_memory_kept() {
+  long_lived_sandbox.create_sandbox();
+
+  tainted_guest<char*> sandbox_buffer = long_lived_sandbox.malloc_in_sandbox<char>(8);
+  kept_buffer = sandbox_buffer.UNSAFE_unverified();
+
+  return 0;
+}
+
+// should not warn: the block is freed, except where the host aborts and takes the sandbox with it
+int sandbox_memory_abort() {
+  long_lived_sandbox.create_sandbox();
+
+  auto sandbox_flag = long_lived_sandbox.malloc_in_sandbox<int32_t>();
+  long_lived_sandbox.invoke_sandbox_function(hello);
+  release_assert((*sandbox_flag).UNSAFE_unverified() == 0, "guest set the flag\n");
+  long_lived_sandbox.free_in_sandbox(sandbox_flag);
+
+  return 0;
+}
+
+// should fail: the index only gets used when it is one more than a second value from the sandbox
+int sandbox_dependent_reads() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_array = sandbox.malloc_in_sandbox<int32_t[2]>();
+  sandbox.invoke_sandbox_function(hello);
+
+  int32_t host_array[4] = { 100, 200, 300, 400 };
+  int32_t first = (*sandbox_array)[0].UNSAFE_unverified();
+  int32_t second = (*sandbox_array)[1].UNSAFE_unverified();
+  int32_t result = 0;
+  if (first == second + 1) {
+    result = host_array[first];
+  }
+
+  sandbox.destroy_sandbox();
+  return result;
+}
+
+// should fail: the buffer's size is up to the caller, but the guest still rewrites it
+int sandbox_havoc_symbolic_size(uint32_t count) {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  tainted_guest<char*> sandbox_buffer = sandbox.malloc_in_sandbox<char>(count);
+  if (sandbox_buffer == nullptr || count == 0) {
+    sandbox.destroy_sandbox();
+    return 0;
+  }
+  *sandbox_buffer = 0;
+  sandbox.invoke_sandbox_function(hello);
+
+  int32_t host_array[4] = { 100, 200, 300, 400 };
+  unsigned char index = (*sandbox_buffer).UNSAFE_unverified();
+  int32_t value = host_array[index];
+
+  sandbox.destroy_sandbox();
+  return value;
+}
+
+// The element comes back from `UNSAFE_unverified` and goes straight out again
+int32_t first_sandbox_element(tainted_guest<int32_t*> sandbox_array) {
+  return sandbox_array[0].UNSAFE_unverified();
+}
+
+// Returns a call's result directly, with nothing between the call and the `ret`
+int32_t passed_on_sandbox_element(tainted_guest<int32_t*> sandbox_array) {
+  return first_sandbox_element(sandbox_array);
+}
+
+// should be safe, but warned about: the index is in bounds, but still comes from the sandbox
+// after being passed back up through two returns
+int sandbox_index_returned_directly() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_array = sandbox.malloc_in_sandbox<int32_t>(4);
+  sandbox_array[0] = 2;
+
+  int32_t host_array[4] = { 100, 200, 300, 400 };
+  int32_t index = passed_on_sandbox_element(sandbox_array);
+  int32_t value = host_array[index];
+
+  sandbox.destroy_sandbox();
+  return value;
+}
+
+// should be safe, with nothing to warn about: the verifier indexes with the value, but only
+// after checking it
+int sandbox_index_in_validator() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_array = sandbox.malloc_in_sandbox<int32_t[4]>();
+  sandbox.invoke_sandbox_function(hello);
+
+  int32_t host_array[4] = { 100, 200, 300, 400 };
+  auto value = (*sandbox_array)[0].copy_and_verify([&](int32_t index) {
+    if (index >= 0 && index < 4) {
+      return host_array[index];
+    }
+    return 0;
+  });
+
+  sandbox.destroy_sandbox();
+  return value;
+}
+
+// haybale has no model for getpid, so it gives up on the path
+int calls_unmodelled_function() {
+  return getpid();
+}
+
+// safe under either memory model: both raw loads through the pointer see the same value, as
+// only reads through the accessors are freshened
+int sandbox_raw_pointer_read_twice() {
+  rlbox_sandbox_guest sandbox;
+  sandbox.create_sandbox();
+
+  auto sandbox_array = sandbox.malloc_in_sandbox<int32_t>(4);
+  sandbox.invoke_sandbox_function(hello);
+
+  int32_t host_array[4] = { 100, 200, 300, 400 };
+  int32_t* raw = sandbox_array.unverified_safe_pointer_because(4, "the index is checked");
+  int32_t result = 0;
+  if (raw[0] >= 0 && raw[0] < 4) {
+    result = host_array[raw[0]];
+  }
+
+  sandbox.destroy_sandbox();
+  return result;
+}
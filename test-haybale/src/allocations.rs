@@ -0,0 +1,180 @@
+use crate::context::with_context;
+use crate::location::Site;
+use crate::utils::get_function_name;
+use haybale::backend::Backend;
+use haybale::backend::DefaultBackend;
+use haybale::{ExecutionManager, State};
+use llvm_ir::instruction::Call;
+use llvm_ir::{Instruction, Name, Operand, TypeRef};
+
+type BV = <DefaultBackend as Backend>::BV;
+
+/// Width of pointers, and of the sizes we track alongside them
+pub const POINTER_BITS: u32 = 64;
+
+/// Where a block of memory came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocKind {
+    /// `alloca`
+    Stack,
+    /// `malloc` and friends, `operator new`
+    Heap,
+    /// A global variable
+    Global,
+    /// `malloc_in_sandbox`
+    Sandbox,
+    /// A range of sandbox memory RLBox checked before handing the host a pointer into it
+    Granted,
+}
+
+/// A block of memory the program is allowed to access
+#[derive(Debug, Clone)]
+pub struct Allocation {
+    pub base: BV,
+    /// Size in bytes; symbolic for e.g. `malloc(n)`
+    pub size: BV,
+    pub kind: AllocKind,
+    pub site: Site,
+}
+
+impl Allocation {
+    /// The condition under which `[addr, addr + len)` is not entirely inside this allocation
+    pub fn out_of_bounds(&self, addr: &BV, len: &BV) -> BV {
+        let end = self.base.add(&self.size);
+        addr.ult(&self.base).or(&addr.add(len).ugt(&end))
+    }
+}
+
+/// Zero-extend (or truncate) a size or index to pointer width
+pub fn to_pointer_width(bv: &BV) -> BV {
+    let width = bv.get_width();
+    if width < POINTER_BITS {
+        bv.uext(POINTER_BITS - width)
+    } else if width > POINTER_BITS {
+        bv.slice(POINTER_BITS - 1, 0)
+    } else {
+        bv.clone()
+    }
+}
+
+pub fn record_allocation(state: &State<DefaultBackend>, base: BV, size: BV, kind: AllocKind) {
+    let allocation = Allocation {
+        base,
+        size: to_pointer_width(&size),
+        kind,
+        site: Site::current(state),
+    };
+    log::debug!("allocation: {:?}", allocation);
+    with_context(|ctx| ctx.allocations.record(state, allocation));
+}
+
+/// The most recent allocation on this path that starts at `base`
+pub fn allocation_at(state: &State<DefaultBackend>, base: &BV) -> Option<Allocation> {
+    with_context(|ctx| {
+        ctx.allocations
+            .visible(state)
+            .filter(|allocation| state.bvs_must_be_equal(&allocation.base, base).unwrap())
+            .last()
+            .cloned()
+    })
+}
+
+/// The most recent allocation on this path that `addr` must point into. `None` means the
+/// pointer's provenance is unknown: it may be outside every allocation we know of, or point
+/// into one of several.
+pub fn allocation_containing(state: &State<DefaultBackend>, addr: &BV) -> Option<Allocation> {
+    let allocations: Vec<Allocation> =
+        with_context(|ctx| ctx.allocations.visible(state).cloned().collect());
+    let one = state.one(POINTER_BITS);
+    allocations.into_iter().rev().find(|allocation| {
+        let outside = allocation.out_of_bounds(addr, &one);
+        !state
+            .sat_with_extra_constraints(std::iter::once(&outside))
+            .unwrap()
+    })
+}
+
+/// An allocation whose address we only learn once the instruction making it has executed
+pub struct PendingAllocation {
+    func: String,
+    result: Operand,
+    size: BV,
+    kind: AllocKind,
+}
+
+/// Instruction callback recording stack and heap allocations, run before every instruction.
+/// Checkers and hooks share what it records, so it runs whichever checkers are enabled.
+pub fn track_allocations<'a>(
+    instr: &'a Instruction,
+    em: &ExecutionManager<'a, DefaultBackend>,
+) -> haybale::Result<()> {
+    let state = em.state();
+    resolve_pending_allocation(state);
+    match instr {
+        Instruction::Alloca(alloca) => {
+            let element_bytes = state.size_in_bits(&alloca.allocated_type).unwrap() / 8;
+            let count = to_pointer_width(&state.operand_to_bv(&alloca.num_elements)?);
+            let size = state
+                .bv_from_u64(element_bytes.into(), POINTER_BITS)
+                .mul(&count);
+            expect_allocation(
+                state,
+                &alloca.dest,
+                state.type_of(alloca),
+                size,
+                AllocKind::Stack,
+            );
+        }
+        Instruction::Call(call) => {
+            if let (Some(dest), Some(size)) = (&call.dest, heap_allocation_size(state, call)) {
+                expect_allocation(state, dest, state.type_of(call), size, AllocKind::Heap);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn expect_allocation(
+    state: &State<DefaultBackend>,
+    dest: &Name,
+    ty: TypeRef,
+    size: BV,
+    kind: AllocKind,
+) {
+    let pending = PendingAllocation {
+        func: state.cur_loc.func.name.clone(),
+        result: Operand::LocalOperand {
+            name: dest.clone(),
+            ty,
+        },
+        size,
+        kind,
+    };
+    with_context(|ctx| ctx.pending_allocation = Some(pending));
+}
+
+// The first instruction back in the allocating function comes right after the allocation, so
+// its result is available by then
+fn resolve_pending_allocation(state: &State<DefaultBackend>) {
+    let pending = with_context(|ctx| match &ctx.pending_allocation {
+        Some(pending) if pending.func == state.cur_loc.func.name => ctx.pending_allocation.take(),
+        _ => None,
+    });
+    if let Some(pending) = pending {
+        if let Ok(base) = state.operand_to_bv(&pending.result) {
+            record_allocation(state, base, pending.size, pending.kind);
+        }
+    }
+}
+
+// Size in bytes of the block a call to an allocator returns, if `call` is one
+fn heap_allocation_size(state: &State<DefaultBackend>, call: &Call) -> Option<BV> {
+    let arg = |i: usize| to_pointer_width(&state.operand_to_bv(&call.arguments[i].0).unwrap());
+    match get_function_name(call)? {
+        "malloc" | "_Znwm" | "_Znam" => Some(arg(0)),
+        "calloc" => Some(arg(0).mul(&arg(1))),
+        "realloc" => Some(arg(1)),
+        _ => None,
+    }
+}
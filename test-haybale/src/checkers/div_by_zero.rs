@@ -1,54 +1,69 @@
-use super::CheckResult;
-use crate::checkers::ExecutionTrace;
-use haybale::Error;
+use super::{CheckErr, CheckResult, Checker, Violation};
 use haybale::ExecutionManager;
 use haybale::State;
+use haybale::backend::Backend;
 use haybale::backend::DefaultBackend;
 use llvm_ir::Instruction;
 use llvm_ir::instruction::BinaryOp;
 
-const DIV_BY_ZERO_ERROR: &str = "CheckErr::DividedByZero";
+pub const CHECKER_ID: &str = "div_by_zero";
 
-// Currently just checks whether the monitor reported an error
-pub fn check_div_by_zero(trace: &ExecutionTrace<'_>) -> CheckResult {
-    let (result, _state) = trace;
-    if let Err(Error::OtherError(error)) = result {
-        if error == DIV_BY_ZERO_ERROR {
-            return Err(Error::OtherError(DIV_BY_ZERO_ERROR.to_string()));
-        }
+pub struct DivByZeroChecker;
+
+impl Checker for DivByZeroChecker {
+    fn name(&self) -> &'static str {
+        CHECKER_ID
+    }
+
+    fn description(&self) -> &'static str {
+        "Integer division or remainder by a value that can be zero"
+    }
+
+    fn monitor<'a>(
+        &self,
+        instr: &'a Instruction,
+        em: &ExecutionManager<'a, DefaultBackend>,
+    ) -> CheckResult {
+        monitor_div_by_zero(instr, em)
     }
-    Ok(())
 }
 
 pub fn monitor_div_by_zero(
     instr: &llvm_ir::Instruction,
     em: &ExecutionManager<'_, DefaultBackend>,
 ) -> CheckResult {
-    match instr {
-        Instruction::UDiv(i) => {
-            can_be_zero(em.state(), i.get_operand1())?;
-        }
-        Instruction::SDiv(i) => {
-            can_be_zero(em.state(), i.get_operand1())?;
-        }
-        Instruction::URem(i) => {
-            can_be_zero(em.state(), i.get_operand1())?;
-        }
-        Instruction::SRem(i) => {
-            can_be_zero(em.state(), i.get_operand1())?;
-        }
-        _ => {}
+    let denominator = match instr {
+        Instruction::UDiv(i) => i.get_operand1(),
+        Instruction::SDiv(i) => i.get_operand1(),
+        Instruction::URem(i) => i.get_operand1(),
+        Instruction::SRem(i) => i.get_operand1(),
+        _ => return Ok(()),
+    };
+    if let Some(is_zero) = can_be_zero(em.state(), denominator) {
+        let denominator_bv = em.state().operand_to_bv(denominator).unwrap();
+        return Err(Violation::new(
+            CheckErr::DividedByZero,
+            CHECKER_ID,
+            &em.state().cur_loc.func.name,
+            instr,
+        )
+        .with_condition(is_zero)
+        .with_value("denominator", denominator_bv));
     }
     Ok(())
 }
 
-fn can_be_zero(state: &State<'_, DefaultBackend>, operand: &llvm_ir::Operand) -> CheckResult {
+// Returns the condition under which `operand` is zero, if that can happen on this path
+fn can_be_zero(
+    state: &State<'_, DefaultBackend>,
+    operand: &llvm_ir::Operand,
+) -> Option<<DefaultBackend as Backend>::BV> {
     let bv = state.operand_to_bv(operand).unwrap();
     let zero = state.zero(bv.get_width());
-    // We will assert that value is zero, and check if state is sat
-    bv._eq(&zero).assert();
-    if state.sat().unwrap() {
-        return Err(Error::OtherError(DIV_BY_ZERO_ERROR.to_string()));
-    }
-    Ok(())
+    // Ask whether the value can be zero without asserting it on the path itself
+    let is_zero = bv._eq(&zero);
+    state
+        .sat_with_extra_constraints(std::iter::once(&is_zero))
+        .unwrap()
+        .then_some(is_zero)
 }
@@ -0,0 +1,105 @@
+use super::{CheckErr, Checker, Violation};
+use crate::context::with_context;
+use crate::location::Site;
+use haybale::State;
+use haybale::backend::Backend;
+use haybale::backend::DefaultBackend;
+
+pub const CHECKER_ID: &str = "double_fetch";
+
+type BV = <DefaultBackend as Backend>::BV;
+
+// With this checker on, a second read of a sandbox location that nothing wrote to since the
+// first one returns a fresh value rather than the stored one (see `read_sandbox_memory`).
+// Every other checker then runs as usual; when one of them reports a violation that can only
+// happen if the two reads differ, `explain` turns it into a double fetch. Because it changes
+// what every run sees, it is not in `Registry::default`; register it to opt in.
+pub struct DoubleFetchChecker;
+
+impl Checker for DoubleFetchChecker {
+    fn name(&self) -> &'static str {
+        CHECKER_ID
+    }
+
+    fn description(&self) -> &'static str {
+        "Sandbox memory read twice, where the second value is used as if it had passed the checks made on the first"
+    }
+
+    fn prepare(&self) {
+        with_context(|ctx| ctx.track_double_fetches = true);
+    }
+}
+
+/// A sandbox location the host read a second time, without writing to it in between
+#[derive(Debug, Clone)]
+pub struct DoubleFetch {
+    pub first: Site,
+    pub second: Site,
+    /// What the location held at the first read
+    pub first_value: BV,
+    /// What the second read returned
+    pub second_value: BV,
+}
+
+impl DoubleFetch {
+    /// The condition under which both reads agree, as they must with no other guest thread
+    fn agree(&self) -> BV {
+        self.first_value._eq(&self.second_value)
+    }
+}
+
+pub fn record_double_fetch(state: &State<DefaultBackend>, double_fetch: DoubleFetch) {
+    log::debug!("double fetch: {:?}", double_fetch);
+    with_context(|ctx| ctx.double_fetches.record(state, double_fetch));
+}
+
+/// If `violation` only happens when some double fetch on this path returns two different
+/// values, the real bug is that double fetch: report it instead, with both read sites
+pub fn explain(
+    state: &State<DefaultBackend>,
+    violation: &Violation,
+) -> haybale::Result<Option<Violation>> {
+    let Some(condition) = &violation.condition else {
+        return Ok(None);
+    };
+    let double_fetches: Vec<DoubleFetch> =
+        with_context(|ctx| ctx.double_fetches.visible(state).cloned().collect());
+    if double_fetches.is_empty() {
+        return Ok(None);
+    }
+    let all_agree = double_fetches
+        .iter()
+        .map(DoubleFetch::agree)
+        .reduce(|all, agree| all.and(&agree))
+        .unwrap();
+    if state.sat_with_extra_constraints([condition, &all_agree].into_iter())? {
+        return Ok(None);
+    }
+    // Blame the first double fetch that is enough on its own to rule the violation out
+    let mut culprit = &double_fetches[0];
+    for double_fetch in &double_fetches {
+        if !state.sat_with_extra_constraints([condition, &double_fetch.agree()].into_iter())? {
+            culprit = double_fetch;
+            break;
+        }
+    }
+    let mut explained = Violation::new(
+        CheckErr::DoubleFetch,
+        CHECKER_ID,
+        &violation.func,
+        &violation.instr,
+    )
+    .with_condition(condition.and(&all_agree.not()))
+    .with_value("first read", culprit.first_value.clone())
+    .with_value("second read", culprit.second_value.clone())
+    .with_related("first read", culprit.first.clone())
+    .with_related("second read", culprit.second.clone());
+    // The second read came straight from the sandbox
+    explained.sandbox_controlled = true;
+    explained.values.extend(violation.values.iter().cloned());
+    explained.related.push((
+        format!("{} if the reads differ", violation.kind),
+        Site::current(state),
+    ));
+    Ok(Some(explained))
+}
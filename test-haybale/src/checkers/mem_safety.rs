@@ -0,0 +1,173 @@
+use super::{CheckErr, CheckResult, Checker, Violation};
+use crate::allocations::{
+    AllocKind, Allocation, POINTER_BITS, allocation_at, allocation_containing, to_pointer_width,
+};
+use crate::location::Site;
+use crate::utils::get_function_name;
+use haybale::ExecutionManager;
+use haybale::State;
+use haybale::backend::Backend;
+use haybale::backend::DefaultBackend;
+use llvm_ir::instruction::Call;
+use llvm_ir::{Constant, Function, Instruction, Operand, TypeRef};
+
+pub const CHECKER_ID: &str = "mem_safety";
+
+type BV = <DefaultBackend as Backend>::BV;
+
+pub struct MemSafetyChecker;
+
+impl Checker for MemSafetyChecker {
+    fn name(&self) -> &'static str {
+        CHECKER_ID
+    }
+
+    fn description(&self) -> &'static str {
+        "Load, store or memory copy/fill that can reach outside the allocation its pointer derives from"
+    }
+
+    fn monitor<'a>(
+        &self,
+        instr: &'a Instruction,
+        em: &ExecutionManager<'a, DefaultBackend>,
+    ) -> CheckResult {
+        monitor_mem_safety(instr, em)
+    }
+}
+
+pub fn monitor_mem_safety<'a>(
+    instr: &'a Instruction,
+    em: &ExecutionManager<'a, DefaultBackend>,
+) -> CheckResult {
+    let state = em.state();
+    match instr {
+        Instruction::Call(call) => check_memory_intrinsic(state, instr, call),
+        Instruction::Load(load) => {
+            let len = access_size(state, &state.type_of(load));
+            check_access(state, instr, &load.address, &len)
+        }
+        Instruction::Store(store) => {
+            let len = access_size(state, &state.type_of(&store.value));
+            check_access(state, instr, &store.address, &len)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_memory_intrinsic(
+    state: &State<DefaultBackend>,
+    instr: &Instruction,
+    call: &Call,
+) -> CheckResult {
+    let Some(name) = get_function_name(call) else {
+        return Ok(());
+    };
+    let args = &call.arguments;
+    if name.starts_with("llvm.memcpy")
+        || name.starts_with("llvm.memmove")
+        || matches!(name, "memcpy" | "memmove")
+    {
+        let len = to_pointer_width(&state.operand_to_bv(&args[2].0).unwrap());
+        check_access(state, instr, &args[0].0, &len)?;
+        check_access(state, instr, &args[1].0, &len)?;
+    } else if name.starts_with("llvm.memset") || matches!(name, "memset" | "strncpy") {
+        // strncpy always writes all `n` bytes of its destination, padding with zeroes
+        let len = to_pointer_width(&state.operand_to_bv(&args[2].0).unwrap());
+        check_access(state, instr, &args[0].0, &len)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn access_size(state: &State<DefaultBackend>, ty: &TypeRef) -> BV {
+    let bytes = state.size_in_bits(ty).unwrap().div_ceil(8);
+    state.bv_from_u32(bytes, POINTER_BITS)
+}
+
+fn check_access(
+    state: &State<DefaultBackend>,
+    instr: &Instruction,
+    pointer: &Operand,
+    len: &BV,
+) -> CheckResult {
+    let addr = state.operand_to_bv(pointer).unwrap();
+    let Some(allocation) = find_allocation(state, pointer, &addr) else {
+        // Unknown provenance: memory we know nothing about, e.g. haybale's own allocations, or
+        // a pointer that could be into any of several
+        return Ok(());
+    };
+    let is_oob = allocation.out_of_bounds(&addr, len);
+    if state
+        .sat_with_extra_constraints(std::iter::once(&is_oob))
+        .unwrap()
+    {
+        return Err(
+            Violation::new(CheckErr::Oob, CHECKER_ID, &state.cur_loc.func.name, instr)
+                .with_condition(is_oob)
+                .with_value("address", addr)
+                .with_value("allocation base", allocation.base)
+                .with_value("allocation size", allocation.size),
+        );
+    }
+    Ok(())
+}
+
+/// The allocation `pointer` (whose value is `addr`) was derived from
+pub fn find_allocation(
+    state: &State<DefaultBackend>,
+    pointer: &Operand,
+    addr: &BV,
+) -> Option<Allocation> {
+    let by_provenance = match provenance_root(state.cur_loc.func, pointer) {
+        Root::Global(global, ty) => Some(Allocation {
+            base: state.const_to_bv(global).unwrap(),
+            size: state.bv_from_u32(state.size_in_bits(ty)? / 8, POINTER_BITS),
+            kind: AllocKind::Global,
+            site: Site::current(state),
+        }),
+        Root::Value(root) => allocation_at(state, &state.operand_to_bv(root).unwrap()),
+        Root::Unknown => None,
+    };
+    // Pointers loaded from memory or passed in lose their provenance; fall back to the
+    // allocation the address must land in, if there is one
+    by_provenance.or_else(|| allocation_containing(state, addr))
+}
+
+enum Root<'p> {
+    Value(&'p Operand),
+    Global(&'p Constant, &'p TypeRef),
+    /// e.g. a null or inttoptr constant
+    Unknown,
+}
+
+// Follow GEPs and casts back to the pointer they were derived from
+fn provenance_root<'p>(func: &'p Function, mut pointer: &'p Operand) -> Root<'p> {
+    loop {
+        let name = match pointer {
+            Operand::LocalOperand { name, .. } => name,
+            Operand::ConstantOperand(constant) => return constant_root(constant),
+            Operand::MetadataOperand => return Root::Value(pointer),
+        };
+        let def = func
+            .basic_blocks
+            .iter()
+            .flat_map(|bb| &bb.instrs)
+            .find(|instr| instr.try_get_result() == Some(name));
+        pointer = match def {
+            Some(Instruction::GetElementPtr(gep)) => &gep.address,
+            Some(Instruction::BitCast(cast)) => &cast.operand,
+            Some(Instruction::AddrSpaceCast(cast)) => &cast.operand,
+            _ => return Root::Value(pointer),
+        };
+    }
+}
+
+fn constant_root(mut constant: &Constant) -> Root<'_> {
+    loop {
+        constant = match constant {
+            Constant::GetElementPtr(gep) => gep.address.as_ref(),
+            Constant::BitCast(cast) => cast.operand.as_ref(),
+            Constant::GlobalReference { ty, .. } => return Root::Global(constant, ty),
+            _ => return Root::Unknown,
+        };
+    }
+}
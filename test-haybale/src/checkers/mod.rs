@@ -1,17 +1,240 @@
 pub mod div_by_zero;
+pub mod double_fetch;
+pub mod mem_safety;
 pub mod null_deref;
 pub mod oob;
+pub mod registry;
+pub mod sandbox_escape;
+pub mod sandbox_free;
+pub mod sandbox_lifecycle;
+pub mod sandbox_memory_leak;
+pub mod sandbox_string;
+pub mod tainted_use;
 
+pub use registry::{Checker, Registry};
+
+use crate::counterexample::Counterexample;
+use crate::location::{Frame, Site, SourceLoc, TraceStep, block_trace, callstack};
+use haybale::config::Demangling;
 use haybale::{Error, ReturnValue, State, backend::DefaultBackend};
+use llvm_ir::DebugLoc;
+use std::fmt;
 
 pub type PathResult = Result<ReturnValue<<DefaultBackend as haybale::backend::Backend>::BV>, Error>;
-pub type ExecutionTrace<'a> = (PathResult, State<'a, DefaultBackend>);
 
-pub type CheckResult = Result<(), Error>;
+/// One explored path: haybale's result for it, the final state, and the violations monitors
+/// reported along it
+pub struct ExecutionTrace<'a> {
+    pub result: PathResult,
+    pub state: State<'a, DefaultBackend>,
+    pub violations: Vec<Violation>,
+}
+
+/// The kinds of property violations our checkers report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CheckErr {
+    Oob,
+    DividedByZero,
+    DereferencedNull,
+    DoubleFetch,
+    UncheckedTaintedUse,
+    UnverifiedString,
+    SandboxEscape,
+    UseBeforeCreate,
+    UseAfterDestroy,
+    DoubleDestroy,
+    SandboxLeak,
+    UseAfterFree,
+    DoubleFree,
+    InvalidFree,
+    SandboxMemoryLeak,
+    /// Reported by a checker registered from outside this crate
+    Custom(&'static str),
+}
+
+impl CheckErr {
+    /// The variant's name, or the custom kind's name
+    pub fn name(&self) -> &'static str {
+        match self {
+            CheckErr::Oob => "Oob",
+            CheckErr::DividedByZero => "DividedByZero",
+            CheckErr::DereferencedNull => "DereferencedNull",
+            CheckErr::DoubleFetch => "DoubleFetch",
+            CheckErr::UncheckedTaintedUse => "UncheckedTaintedUse",
+            CheckErr::UnverifiedString => "UnverifiedString",
+            CheckErr::SandboxEscape => "SandboxEscape",
+            CheckErr::UseBeforeCreate => "UseBeforeCreate",
+            CheckErr::UseAfterDestroy => "UseAfterDestroy",
+            CheckErr::DoubleDestroy => "DoubleDestroy",
+            CheckErr::SandboxLeak => "SandboxLeak",
+            CheckErr::UseAfterFree => "UseAfterFree",
+            CheckErr::DoubleFree => "DoubleFree",
+            CheckErr::InvalidFree => "InvalidFree",
+            CheckErr::SandboxMemoryLeak => "SandboxMemoryLeak",
+            CheckErr::Custom(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for CheckErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CheckErr::{}", self.name())
+    }
+}
+
+/// How bad a violation is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Severity {
+    /// The property is broken on this path
+    #[default]
+    Error,
+    /// Nothing goes wrong on this path, but the code relies on something it doesn't check.
+    /// Warnings never end a path and are kept apart from errors in the results.
+    Warning,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A violation found by a checker, along with where it happened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub kind: CheckErr,
+    pub severity: Severity,
+    /// Id of the checker that reported this violation
+    pub checker: &'static str,
+    /// Name of the function containing the offending instruction
+    pub func: String,
+    /// `func`, demangled
+    pub demangled_func: String,
+    /// The offending instruction
+    pub instr: String,
+    /// Source location of the offending instruction, if it has debug info
+    pub loc: Option<SourceLoc>,
+    /// Calls leading to the offending instruction, outermost first
+    pub callstack: Vec<Frame>,
+    /// Basic blocks the path went through to reach the offending instruction
+    pub trace: Vec<TraceStep>,
+    /// Other program points involved, e.g. both reads of a double fetch
+    pub related: Vec<(String, Site)>,
+    /// Whether the offending instruction consumes a value the sandbox can influence
+    pub sandbox_controlled: bool,
+    /// Solver condition under which the violation happens, if the checker can express one.
+    /// Exhaustive runs assume its negation to keep exploring the path.
+    pub condition: Option<<DefaultBackend as haybale::backend::Backend>::BV>,
+    /// Values of interest to whoever fixes the bug, e.g. the offending index
+    pub values: Vec<(String, <DefaultBackend as haybale::backend::Backend>::BV)>,
+    /// Concrete inputs that trigger the violation
+    pub counterexample: Option<Counterexample>,
+}
+
+impl Violation {
+    pub fn new(kind: CheckErr, checker: &'static str, func: &str, instr: impl ToString) -> Self {
+        Violation {
+            kind,
+            severity: Severity::Error,
+            checker,
+            func: func.to_string(),
+            demangled_func: func.to_string(),
+            instr: instr.to_string(),
+            loc: None,
+            callstack: Vec::new(),
+            trace: Vec::new(),
+            related: Vec::new(),
+            sandbox_controlled: false,
+            condition: None,
+            values: Vec::new(),
+            counterexample: None,
+        }
+    }
+
+    /// Fill in where the violation happened, given the state at the offending instruction
+    /// and that instruction's debug location
+    pub fn locate(&mut self, state: &State<DefaultBackend>, debug_loc: Option<&DebugLoc>) {
+        self.demangled_func = Demangling::autodetect(state.proj).maybe_demangle(&self.func);
+        self.loc = debug_loc.map(SourceLoc::from);
+        self.callstack = callstack(state);
+        self.trace = block_trace(state);
+        // The innermost frame is at the offending instruction itself
+        if let Some(frame) = self.callstack.last_mut() {
+            frame.loc = self.loc.clone();
+        }
+    }
+
+    pub fn with_condition(
+        mut self,
+        condition: <DefaultBackend as haybale::backend::Backend>::BV,
+    ) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    pub fn with_value(
+        mut self,
+        name: impl ToString,
+        value: <DefaultBackend as haybale::backend::Backend>::BV,
+    ) -> Self {
+        self.values.push((name.to_string(), value));
+        self
+    }
+
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    pub fn with_related(mut self, label: impl ToString, site: Site) -> Self {
+        self.related.push((label.to_string(), site));
+        self
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.severity == Severity::Warning {
+            write!(f, "warning: ")?;
+        }
+        write!(f, "{}", self.kind)?;
+        if self.sandbox_controlled {
+            write!(f, " (sandbox-controlled)")?;
+        }
+        write!(f, " in {}", self.demangled_func)?;
+        if let Some(loc) = &self.loc {
+            write!(f, " at {}", loc)?;
+        }
+        write!(f, ": {}", self.instr)
+    }
+}
+
+/// Result of a monitor looking at a single instruction
+pub type CheckResult = Result<(), Violation>;
+
+/// Result of checking a whole path: every violation found along it
+pub type TraceResult = Result<(), Vec<Violation>>;
 
-pub fn check_trace(trace: &ExecutionTrace<'_>) -> CheckResult {
-    null_deref::check_null_deref(trace)?;
-    div_by_zero::check_div_by_zero(trace)?;
-    oob::check_oob(trace)?;
-    Ok(())
+/// Combine what the monitors reported along the path with each enabled checker's verdict on it.
+/// Errors make up the path's result; warnings are returned on their own.
+pub fn check_trace(
+    trace: &ExecutionTrace<'_>,
+    checkers: &Registry,
+) -> (TraceResult, Vec<Violation>) {
+    let mut violations = trace.violations.clone();
+    for checker in checkers.enabled() {
+        violations.extend(checker.check_path(trace));
+    }
+    let (errors, warnings): (Vec<Violation>, Vec<Violation>) = violations
+        .into_iter()
+        .partition(|violation| violation.severity == Severity::Error);
+    let result = if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    };
+    (result, warnings)
 }
@@ -1,12 +1,42 @@
-use super::CheckResult;
+use super::{CheckErr, Checker, Violation};
 use crate::checkers::ExecutionTrace;
+use crate::counterexample::Counterexample;
+use crate::taint::current_instr_is_tainted;
+use crate::utils::current_instr;
 use haybale::Error;
 
+pub const CHECKER_ID: &str = "null_deref";
+
+pub struct NullDerefChecker;
+
+impl Checker for NullDerefChecker {
+    fn name(&self) -> &'static str {
+        CHECKER_ID
+    }
+
+    fn description(&self) -> &'static str {
+        "Load or store through a pointer that can be null"
+    }
+
+    fn check_path(&self, trace: &ExecutionTrace<'_>) -> Vec<Violation> {
+        check_null_deref(trace)
+    }
+}
+
 // Haybale already reports null pointer dereferences, so we just check for that
-pub fn check_null_deref(trace: &ExecutionTrace<'_>) -> CheckResult {
-    let (result, _state) = trace;
-    if let Err(Error::NullPointerDereference) = result {
-        return Err(Error::OtherError("CheckErr::DereferencedNull".to_string()));
+pub fn check_null_deref(trace: &ExecutionTrace<'_>) -> Vec<Violation> {
+    if let Err(Error::NullPointerDereference) = trace.result {
+        let mut violation = Violation::new(
+            CheckErr::DereferencedNull,
+            CHECKER_ID,
+            &trace.state.cur_loc.func.name,
+            current_instr(&trace.state),
+        );
+        violation.locate(&trace.state, trace.state.cur_loc.source_loc);
+        violation.sandbox_controlled = current_instr_is_tainted(&trace.state);
+        // Haybale doesn't tell us the null condition, so this is just a model of the path
+        violation.counterexample = Counterexample::solve(&trace.state, None, &[]).unwrap();
+        return vec![violation];
     }
-    Ok(())
+    Vec::new()
 }
@@ -1,26 +1,77 @@
-use super::CheckResult;
-// use crate::checkers::CheckErr;
-use crate::checkers::ExecutionTrace;
+use super::{CheckErr, CheckResult, Checker, Violation};
+use crate::context::with_context;
 use crate::utils::get_pointer_type;
-use haybale::Error;
 use haybale::ExecutionManager;
+use haybale::State;
 use haybale::backend::Backend;
 use haybale::backend::DefaultBackend;
-use llvm_ir::Instruction;
-use llvm_ir::TypeRef;
+use llvm_ir::Operand;
 use llvm_ir::instruction::GetElementPtr;
+use llvm_ir::types::NamedStructDef;
+use llvm_ir::{Instruction, Name};
+use llvm_ir::{Type, TypeRef};
 
-const OOB_ERROR: &str = "CheckErr::Oob";
+pub const CHECKER_ID: &str = "oob";
 
-// Currently just checks whether the monitor reported an error
-pub fn check_oob(trace: &ExecutionTrace<'_>) -> CheckResult {
-    let (result, _state) = trace;
-    if let Err(Error::OtherError(error)) = result {
-        if error == OOB_ERROR {
-            return Err(Error::OtherError(OOB_ERROR.to_string()));
+type BV = <DefaultBackend as Backend>::BV;
+
+#[derive(Default)]
+pub struct OobChecker {
+    /// Also check every index against its own array dimension, and pointers into struct
+    /// fields against the field, like `-fsanitize=bounds` does
+    pub strict: bool,
+}
+
+impl OobChecker {
+    pub fn strict() -> Self {
+        OobChecker { strict: true }
+    }
+}
+
+/// A struct field a pointer was derived from, which the pointer must stay inside. Recorded for
+/// the result of each GEP that selects a field, and for each GEP on a pointer that has one.
+#[derive(Debug, Clone)]
+pub struct SubObject {
+    /// The function and SSA name of the pointer
+    pub func: String,
+    pub pointer: Name,
+    pub base: BV,
+    /// Size in bytes, same width as `base`
+    pub size: BV,
+}
+
+impl SubObject {
+    // One past the end of the field is a fine address to compute, as long as nothing reads
+    // through it, which is for mem_safety to check
+    fn excludes(&self, addr: &BV) -> BV {
+        addr.ult(&self.base)
+            .or(&addr.ugt(&self.base.add(&self.size)))
+    }
+}
+
+impl Checker for OobChecker {
+    fn name(&self) -> &'static str {
+        CHECKER_ID
+    }
+
+    fn description(&self) -> &'static str {
+        if self.strict {
+            "inbounds GetElementPtr with an index outside its array dimension or a result outside the struct field it started from"
+        } else {
+            "inbounds GetElementPtr whose offset can exceed the size of the aggregate it indexes"
         }
     }
-    Ok(())
+
+    fn monitor<'a>(
+        &self,
+        instr: &'a Instruction,
+        em: &ExecutionManager<'a, DefaultBackend>,
+    ) -> CheckResult {
+        if self.strict {
+            monitor_oob_strict(instr, em)?;
+        }
+        monitor_oob(instr, em)
+    }
 }
 
 // takes in ptr to aggregate and returns width of that aggregate
@@ -71,11 +122,193 @@ pub fn monitor_oob<'a>(
         // calculate size of GEP aggregate as BV
         let sz_bv = size_of_aggregate(&ty, em, offset.get_width());
 
-        // check if its possible that offset >= size of aggregate
-        offset.ugte(&sz_bv).assert();
-        if em.state().sat().unwrap() {
-            return Err(Error::OtherError(OOB_ERROR.to_string()));
+        // check if its possible that offset >= size of aggregate, leaving the path condition untouched
+        let is_oob = offset.ugte(&sz_bv);
+        if em
+            .state()
+            .sat_with_extra_constraints(std::iter::once(&is_oob))
+            .unwrap()
+        {
+            let mut violation = Violation::new(
+                CheckErr::Oob,
+                CHECKER_ID,
+                &em.state().cur_loc.func.name,
+                instr,
+            )
+            .with_condition(is_oob)
+            .with_value("offset", offset);
+            for index in &i.indices {
+                if let Operand::LocalOperand { name, .. } = index {
+                    let index_bv = em.state().operand_to_bv(index).unwrap();
+                    violation = violation.with_value(format!("index {}", name), index_bv);
+                }
+            }
+            return Err(violation);
         }
     }
     Ok(())
 }
+
+// Strict mode. The total offset check above folds all indices into one byte offset, so
+// `arr[0][5]` on an `int[2][3]` still lands inside `arr`, and so does running off the end of
+// one struct field into the next. Check each index against its own dimension instead, and
+// remember which field each GEP result points into so pointer arithmetic on it can be checked
+// later. Like `-fsanitize=bounds`, we let the last index point one past the end of its array,
+// as in `&arr[N]`; dereferencing that is left to mem_safety.
+pub fn monitor_oob_strict<'a>(
+    instr: &'a Instruction,
+    em: &ExecutionManager<'a, DefaultBackend>,
+) -> CheckResult {
+    let Instruction::GetElementPtr(i) = instr else {
+        return Ok(());
+    };
+    if !i.in_bounds {
+        return Ok(());
+    }
+    let state = em.state();
+    let address_ty = state.type_of(&i.address);
+    let offset = compute_gep_offset(&address_ty, em, i);
+    let parent = subobject_of(state, &i.address);
+    // Only reported once the result's field is recorded, so that in exhaustive mode pointer
+    // arithmetic on it is still checked
+    let mut violation = match &parent {
+        Some(field) => check_subobject(state, instr, i, field, &offset).err(),
+        None => None,
+    };
+
+    let mut ty = get_pointer_type(&address_ty);
+    let mut selects_field = false;
+    let last = i.indices.len().saturating_sub(1);
+    // The first index steps over whole objects, which the total offset check covers
+    for (position, index) in i.indices.iter().enumerate().skip(1) {
+        let index_bv = state.operand_to_bv(index).unwrap();
+        ty = match resolve_named_struct(state, &ty).as_ref() {
+            Type::ArrayType {
+                element_type,
+                num_elements,
+            }
+            | Type::VectorType {
+                element_type,
+                num_elements,
+                ..
+            } => {
+                let bound = state.bv_from_u64(*num_elements as u64, index_bv.get_width());
+                // unsigned comparison, so negative indices are out of bounds too
+                let is_oob = if position == last {
+                    index_bv.ugt(&bound)
+                } else {
+                    index_bv.ugte(&bound)
+                };
+                if violation.is_none()
+                    && state
+                        .sat_with_extra_constraints(std::iter::once(&is_oob))
+                        .unwrap()
+                {
+                    violation = Some(
+                        Violation::new(CheckErr::Oob, CHECKER_ID, &state.cur_loc.func.name, instr)
+                            .with_condition(is_oob)
+                            .with_value("index", index_bv)
+                            .with_value("dimension", bound),
+                    );
+                }
+                selects_field = false;
+                element_type.clone()
+            }
+            // struct indices are always constants
+            Type::StructType { element_types, .. } => {
+                selects_field = true;
+                element_types[index_bv.as_u64().unwrap() as usize].clone()
+            }
+            _ => return violation.map_or(Ok(()), Err),
+        };
+    }
+
+    let field = if selects_field {
+        let base = state.operand_to_bv(&i.address).unwrap().add(&offset);
+        let size_in_bytes = state.size_in_bits(&ty).unwrap() / 8;
+        Some((
+            base.clone(),
+            state.bv_from_u32(size_in_bytes, base.get_width()),
+        ))
+    } else {
+        // Pointer arithmetic inside a field stays bound to it
+        parent.map(|field| (field.base, field.size))
+    };
+    if let Some((base, size)) = field {
+        let field = SubObject {
+            func: state.cur_loc.func.name.clone(),
+            pointer: i.dest.clone(),
+            base,
+            size,
+        };
+        with_context(|ctx| ctx.subobjects.record(state, field));
+    }
+    violation.map_or(Ok(()), Err)
+}
+
+// The field `pointer` was derived from, looking through casts. Pointers that went through
+// memory have lost theirs.
+fn subobject_of(state: &State<DefaultBackend>, mut pointer: &Operand) -> Option<SubObject> {
+    let func = state.cur_loc.func;
+    loop {
+        let Operand::LocalOperand { name, .. } = pointer else {
+            return None;
+        };
+        let recorded = with_context(|ctx| {
+            ctx.subobjects
+                .visible(state)
+                .filter(|field| field.func == func.name && field.pointer == *name)
+                .last()
+                .cloned()
+        });
+        if recorded.is_some() {
+            return recorded;
+        }
+        let def = func
+            .basic_blocks
+            .iter()
+            .flat_map(|bb| &bb.instrs)
+            .find(|instr| instr.try_get_result() == Some(name));
+        pointer = match def {
+            Some(Instruction::BitCast(cast)) => &cast.operand,
+            Some(Instruction::AddrSpaceCast(cast)) => &cast.operand,
+            _ => return None,
+        };
+    }
+}
+
+// Check a GEP on a pointer into a struct field stays inside that field
+fn check_subobject(
+    state: &State<DefaultBackend>,
+    instr: &Instruction,
+    i: &GetElementPtr,
+    field: &SubObject,
+    offset: &BV,
+) -> CheckResult {
+    let address = state.operand_to_bv(&i.address).unwrap();
+    let is_oob = field.excludes(&address.add(offset));
+    if state
+        .sat_with_extra_constraints(std::iter::once(&is_oob))
+        .unwrap()
+    {
+        return Err(
+            Violation::new(CheckErr::Oob, CHECKER_ID, &state.cur_loc.func.name, instr)
+                .with_condition(is_oob)
+                .with_value("offset", offset.clone())
+                .with_value("field base", field.base.clone())
+                .with_value("field size", field.size.clone()),
+        );
+    }
+    Ok(())
+}
+
+fn resolve_named_struct(state: &State<DefaultBackend>, ty: &TypeRef) -> TypeRef {
+    if let Type::NamedStructType { name } = ty.as_ref() {
+        if let Some(NamedStructDef::Defined(def)) =
+            state.cur_loc.module.types.named_struct_def(name)
+        {
+            return def.clone();
+        }
+    }
+    ty.clone()
+}
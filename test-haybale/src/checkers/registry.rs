@@ -0,0 +1,131 @@
+use super::div_by_zero::DivByZeroChecker;
+use super::mem_safety::MemSafetyChecker;
+use super::null_deref::NullDerefChecker;
+use super::oob::OobChecker;
+use super::sandbox_escape::SandboxEscapeChecker;
+use super::sandbox_free::SandboxFreeChecker;
+use super::sandbox_lifecycle::SandboxLifecycleChecker;
+use super::sandbox_memory_leak::SandboxMemoryLeakChecker;
+use super::sandbox_string::SandboxStringChecker;
+use super::tainted_use::TaintedUseChecker;
+use super::{CheckResult, ExecutionTrace, Violation};
+use haybale::ExecutionManager;
+use haybale::backend::DefaultBackend;
+use llvm_ir::Instruction;
+use std::fmt;
+use std::rc::Rc;
+
+/// A property checked on every explored path.
+///
+/// A checker can watch instructions as they execute (`monitor`), look at each finished path
+/// (`check_path`), or both.
+pub trait Checker {
+    /// Short unique id, used to enable/disable the checker and to tag its violations
+    fn name(&self) -> &'static str;
+
+    /// One line describing the property being checked
+    fn description(&self) -> &'static str;
+
+    /// Called before each run, once the context has been reset, e.g. to switch on
+    /// bookkeeping the checker relies on
+    fn prepare(&self) {}
+
+    /// Called before every instruction executes
+    fn monitor<'a>(
+        &self,
+        _instr: &'a Instruction,
+        _em: &ExecutionManager<'a, DefaultBackend>,
+    ) -> CheckResult {
+        Ok(())
+    }
+
+    /// Called on every path once exploration is done
+    fn check_path(&self, _trace: &ExecutionTrace<'_>) -> Vec<Violation> {
+        Vec::new()
+    }
+}
+
+/// The set of checkers a run uses, each of which can be switched on and off
+#[derive(Clone)]
+pub struct Registry {
+    checkers: Vec<(Rc<dyn Checker>, bool)>,
+}
+
+impl Registry {
+    pub fn empty() -> Self {
+        Registry {
+            checkers: Vec::new(),
+        }
+    }
+
+    /// Add an enabled checker, replacing any checker with the same name
+    pub fn register(&mut self, checker: impl Checker + 'static) {
+        let checker: Rc<dyn Checker> = Rc::new(checker);
+        match self
+            .checkers
+            .iter_mut()
+            .find(|(existing, _)| existing.name() == checker.name())
+        {
+            Some(entry) => *entry = (checker, true),
+            None => self.checkers.push((checker, true)),
+        }
+    }
+
+    /// Returns false if there is no checker called `name`
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self
+            .checkers
+            .iter_mut()
+            .find(|(checker, _)| checker.name() == name)
+        {
+            Some(entry) => {
+                entry.1 = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn enabled(&self) -> impl Iterator<Item = &Rc<dyn Checker>> {
+        self.checkers
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(checker, _)| checker)
+    }
+
+    /// Every registered checker, whether or not it is enabled
+    pub fn all(&self) -> impl Iterator<Item = &Rc<dyn Checker>> {
+        self.checkers.iter().map(|(checker, _)| checker)
+    }
+}
+
+impl Default for Registry {
+    /// The checkers that ship with this crate. `DoubleFetchChecker` is left out: it changes
+    /// what re-reads of sandbox memory return for every other checker, so runs opt into it.
+    fn default() -> Self {
+        let mut registry = Registry::empty();
+        registry.register(NullDerefChecker);
+        registry.register(DivByZeroChecker);
+        registry.register(OobChecker::default());
+        registry.register(MemSafetyChecker);
+        registry.register(TaintedUseChecker);
+        registry.register(SandboxStringChecker);
+        registry.register(SandboxEscapeChecker);
+        registry.register(SandboxLifecycleChecker);
+        registry.register(SandboxFreeChecker);
+        registry.register(SandboxMemoryLeakChecker);
+        registry
+    }
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.checkers
+                    .iter()
+                    .map(|(checker, enabled)| (checker.name(), enabled)),
+            )
+            .finish()
+    }
+}
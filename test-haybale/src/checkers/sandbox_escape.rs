@@ -0,0 +1,108 @@
+use super::mem_safety::access_size;
+use super::{CheckErr, CheckResult, Checker, Violation};
+use crate::allocations::to_pointer_width;
+use crate::sandbox::range_in_sandbox_memory;
+use crate::taint::is_tainted;
+use crate::utils::get_function_name;
+use haybale::ExecutionManager;
+use haybale::State;
+use haybale::backend::Backend;
+use haybale::backend::DefaultBackend;
+use llvm_ir::{Instruction, Operand};
+
+pub const CHECKER_ID: &str = "sandbox_escape";
+
+type BV = <DefaultBackend as Backend>::BV;
+
+// A pointer the sandbox controls should only ever lead into sandbox memory: if the host follows
+// one anywhere else, the guest gets to read or write host memory through it. RLBox's pointer
+// accessors check this for the host, but `UNSAFE_unverified` and
+// `unverified_safe_pointer_because` take the host's word for it.
+pub struct SandboxEscapeChecker;
+
+impl Checker for SandboxEscapeChecker {
+    fn name(&self) -> &'static str {
+        CHECKER_ID
+    }
+
+    fn description(&self) -> &'static str {
+        "Host access through a sandbox-controlled pointer that can reach outside sandbox memory"
+    }
+
+    fn monitor<'a>(
+        &self,
+        instr: &'a Instruction,
+        em: &ExecutionManager<'a, DefaultBackend>,
+    ) -> CheckResult {
+        monitor_sandbox_escape(instr, em)
+    }
+}
+
+pub fn monitor_sandbox_escape<'a>(
+    instr: &'a Instruction,
+    em: &ExecutionManager<'a, DefaultBackend>,
+) -> CheckResult {
+    let state = em.state();
+    match instr {
+        Instruction::Load(load) => {
+            let len = access_size(state, &state.type_of(load));
+            check_pointer(state, instr, &load.address, &len)
+        }
+        Instruction::Store(store) => {
+            let len = access_size(state, &state.type_of(&store.value));
+            check_pointer(state, instr, &store.address, &len)
+        }
+        Instruction::Call(call) => {
+            let Some(name) = get_function_name(call) else {
+                return Ok(());
+            };
+            let args = &call.arguments;
+            let copies = name.starts_with("llvm.memcpy")
+                || name.starts_with("llvm.memmove")
+                || matches!(name, "memcpy" | "memmove");
+            let fills = name.starts_with("llvm.memset") || matches!(name, "memset" | "strncpy");
+            if !copies && !fills {
+                return Ok(());
+            }
+            let len = to_pointer_width(&state.operand_to_bv(&args[2].0).unwrap());
+            check_pointer(state, instr, &args[0].0, &len)?;
+            if copies {
+                check_pointer(state, instr, &args[1].0, &len)?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_pointer(
+    state: &State<DefaultBackend>,
+    instr: &Instruction,
+    pointer: &Operand,
+    len: &BV,
+) -> CheckResult {
+    if !is_tainted(state, pointer) {
+        return Ok(());
+    }
+    let addr = state.operand_to_bv(pointer).unwrap();
+    // With no sandbox created on this path, we can't tell its memory from the host's
+    let Some(inside) = range_in_sandbox_memory(state, &addr, len) else {
+        return Ok(());
+    };
+    let escapes = inside.not();
+    if state
+        .sat_with_extra_constraints(std::iter::once(&escapes))
+        .unwrap()
+    {
+        return Err(Violation::new(
+            CheckErr::SandboxEscape,
+            CHECKER_ID,
+            &state.cur_loc.func.name,
+            instr,
+        )
+        .with_condition(escapes)
+        .with_value("pointer", addr)
+        .with_value("length", len.clone()));
+    }
+    Ok(())
+}
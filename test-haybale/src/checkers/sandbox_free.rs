@@ -0,0 +1,153 @@
+use super::mem_safety::access_size;
+use super::{CheckErr, CheckResult, Checker, Violation};
+use crate::allocations::{AllocKind, POINTER_BITS};
+use crate::context::with_context;
+use crate::sandbox::{freed_blocks, region_of, tainted_pointer_arg};
+use crate::utils::get_function_name;
+use haybale::ExecutionManager;
+use haybale::State;
+use haybale::backend::Backend;
+use haybale::backend::DefaultBackend;
+use haybale::config::Demangling;
+use llvm_ir::instruction::Call;
+use llvm_ir::{Instruction, Operand};
+
+pub const CHECKER_ID: &str = "sandbox_free";
+
+type BV = <DefaultBackend as Backend>::BV;
+
+// Checks `free_in_sandbox` against the blocks `malloc_in_sandbox` handed out on the path, and
+// host accesses against the blocks freed since. Freed blocks are never handed out again (see
+// `free_in_sandbox`), so any access to one is a use after free.
+pub struct SandboxFreeChecker;
+
+impl Checker for SandboxFreeChecker {
+    fn name(&self) -> &'static str {
+        CHECKER_ID
+    }
+
+    fn description(&self) -> &'static str {
+        "Sandbox memory used after free_in_sandbox, freed twice, or freed without coming from malloc_in_sandbox"
+    }
+
+    fn monitor<'a>(
+        &self,
+        instr: &'a Instruction,
+        em: &ExecutionManager<'a, DefaultBackend>,
+    ) -> CheckResult {
+        monitor_sandbox_free(instr, em)
+    }
+}
+
+pub fn monitor_sandbox_free<'a>(
+    instr: &'a Instruction,
+    em: &ExecutionManager<'a, DefaultBackend>,
+) -> CheckResult {
+    let state = em.state();
+    match instr {
+        Instruction::Call(call) => check_call(state, instr, call),
+        Instruction::Load(load) => {
+            let len = access_size(state, &state.type_of(load));
+            check_access(state, instr, &load.address, &len)
+        }
+        Instruction::Store(store) => {
+            let len = access_size(state, &state.type_of(&store.value));
+            check_access(state, instr, &store.address, &len)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_call(state: &State<DefaultBackend>, instr: &Instruction, call: &Call) -> CheckResult {
+    let Some(name) = get_function_name(call) else {
+        return Ok(());
+    };
+    let demangled = Demangling::autodetect(state.proj).maybe_demangle(name);
+    if demangled.contains("rlbox::rlbox_sandbox<") && demangled.contains("::free_in_sandbox") {
+        return check_free(state, instr, call);
+    }
+    // Accessors of tainted values read the sandbox memory `this` points to
+    match call.arguments.first() {
+        Some((this, _)) if demangled.contains("rlbox::tainted") => {
+            check_access(state, instr, this, &state.one(POINTER_BITS))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_free(state: &State<DefaultBackend>, instr: &Instruction, call: &Call) -> CheckResult {
+    let [(sandbox, _), (pointer, _)] = call.arguments.as_slice() else {
+        return Ok(());
+    };
+    // Blocks of a sandbox we didn't see being set up may have come from anywhere
+    let sandbox = state.operand_to_bv(sandbox).unwrap();
+    if region_of(state, &sandbox).is_none() {
+        return Ok(());
+    }
+    let pointer = tainted_pointer_arg(state, pointer).unwrap();
+    let violation = |kind, condition| {
+        Violation::new(kind, CHECKER_ID, &state.cur_loc.func.name, instr)
+            .with_condition(condition)
+            .with_value("pointer", pointer.clone())
+    };
+
+    for free in freed_blocks(state) {
+        let again = pointer._eq(&free.block.base);
+        if sat(state, &again) {
+            return Err(violation(CheckErr::DoubleFree, again)
+                .with_related("allocated", free.block.site)
+                .with_related("freed", free.site));
+        }
+    }
+
+    // Freeing null does nothing
+    let not_a_block = with_context(|ctx| {
+        ctx.allocations
+            .visible(state)
+            .filter(|allocation| allocation.kind == AllocKind::Sandbox)
+            .fold(
+                pointer._ne(&state.zero(POINTER_BITS)),
+                |not_a_block, block| not_a_block.and(&pointer._ne(&block.base)),
+            )
+    });
+    if sat(state, &not_a_block) {
+        return Err(violation(CheckErr::InvalidFree, not_a_block));
+    }
+    Ok(())
+}
+
+// The host touching a sandbox block that has been freed
+fn check_access(
+    state: &State<DefaultBackend>,
+    instr: &Instruction,
+    pointer: &Operand,
+    len: &BV,
+) -> CheckResult {
+    let freed = freed_blocks(state);
+    if freed.is_empty() {
+        return Ok(());
+    }
+    let addr = state.operand_to_bv(pointer).unwrap();
+    for free in freed {
+        let inside = free.block.out_of_bounds(&addr, len).not();
+        if sat(state, &inside) {
+            return Err(Violation::new(
+                CheckErr::UseAfterFree,
+                CHECKER_ID,
+                &state.cur_loc.func.name,
+                instr,
+            )
+            .with_condition(inside)
+            .with_value("pointer", addr)
+            .with_related("allocated", free.block.site)
+            .with_related("freed", free.site));
+        }
+    }
+    Ok(())
+}
+
+fn sat(state: &State<DefaultBackend>, condition: &BV) -> bool {
+    state
+        .sat_with_extra_constraints(std::iter::once(condition))
+        .unwrap()
+}
@@ -0,0 +1,199 @@
+use super::{CheckErr, CheckResult, Checker, ExecutionTrace, Severity, Violation};
+use crate::allocations::POINTER_BITS;
+use crate::context::with_context;
+use crate::location::Site;
+use crate::sandbox::{Lifecycle, SandboxLifecycle, current_lifecycles, lifecycle_of, region_of};
+use crate::utils::{current_instr, get_function_name};
+use haybale::ExecutionManager;
+use haybale::State;
+use haybale::backend::Backend;
+use haybale::backend::DefaultBackend;
+use haybale::config::Demangling;
+use llvm_ir::instruction::Call;
+use llvm_ir::{Instruction, Operand};
+
+pub const CHECKER_ID: &str = "sandbox_lifecycle";
+
+type BV = <DefaultBackend as Backend>::BV;
+
+// Methods of `rlbox_sandbox` that need the sandbox to be up. Creating it is tracked by its
+// hook, and constructing and dropping the object are fine at any stage.
+const SANDBOX_OPERATIONS: &[&str] = &[
+    "::destroy_sandbox",
+    "::INTERNAL_invoke_with_func_ptr",
+    "::INTERNAL_invoke_with_func_name",
+    "::malloc_in_sandbox",
+    "::free_in_sandbox",
+    "::register_callback",
+];
+
+// Follows each `rlbox_sandbox` object constructed or created on the path through its life,
+// which the hooks on its constructor, `create_sandbox` and `destroy_sandbox` record. Sandboxes
+// the path never saw being set up, e.g. ones passed in from the caller, are left alone, as we
+// don't know what stage they are at.
+pub struct SandboxLifecycleChecker;
+
+impl Checker for SandboxLifecycleChecker {
+    fn name(&self) -> &'static str {
+        CHECKER_ID
+    }
+
+    fn description(&self) -> &'static str {
+        "Sandbox used before create_sandbox or after destroy_sandbox, destroyed twice, or never destroyed"
+    }
+
+    fn monitor<'a>(
+        &self,
+        instr: &'a Instruction,
+        em: &ExecutionManager<'a, DefaultBackend>,
+    ) -> CheckResult {
+        monitor_sandbox_lifecycle(instr, em)
+    }
+
+    fn check_path(&self, trace: &ExecutionTrace<'_>) -> Vec<Violation> {
+        check_sandbox_leaks(trace)
+    }
+}
+
+pub fn monitor_sandbox_lifecycle<'a>(
+    instr: &'a Instruction,
+    em: &ExecutionManager<'a, DefaultBackend>,
+) -> CheckResult {
+    let state = em.state();
+    match instr {
+        Instruction::Call(call) => check_call(state, instr, call),
+        Instruction::Load(load) => check_access(state, instr, &load.address),
+        Instruction::Store(store) => check_access(state, instr, &store.address),
+        _ => Ok(()),
+    }
+}
+
+fn check_call(state: &State<DefaultBackend>, instr: &Instruction, call: &Call) -> CheckResult {
+    let Some(name) = get_function_name(call) else {
+        return Ok(());
+    };
+    let demangled = Demangling::autodetect(state.proj).maybe_demangle(name);
+    let is_operation = demangled.contains("rlbox::rlbox_sandbox<")
+        && SANDBOX_OPERATIONS.iter().any(|op| demangled.contains(op));
+    if !is_operation {
+        // Accessors of tainted values read the sandbox memory `this` points to
+        return match call.arguments.first() {
+            Some((this, _)) if demangled.contains("rlbox::tainted") => {
+                check_access(state, instr, this)
+            }
+            _ => Ok(()),
+        };
+    }
+    // `this` comes first, unless the result is returned through a pointer
+    let Some(lifecycle) = call
+        .arguments
+        .iter()
+        .take(2)
+        .find_map(|(arg, _)| tracked_sandbox(state, arg))
+    else {
+        return Ok(());
+    };
+    let kind = match lifecycle.stage {
+        Lifecycle::Created => return Ok(()),
+        Lifecycle::Constructed => CheckErr::UseBeforeCreate,
+        Lifecycle::Destroyed if demangled.contains("::destroy_sandbox") => CheckErr::DoubleDestroy,
+        Lifecycle::Destroyed => CheckErr::UseAfterDestroy,
+    };
+    let label = match lifecycle.stage {
+        Lifecycle::Constructed => "sandbox constructed",
+        _ => "sandbox destroyed",
+    };
+    Err(
+        Violation::new(kind, CHECKER_ID, &state.cur_loc.func.name, instr)
+            .with_value("sandbox", lifecycle.sandbox)
+            .with_related(label, lifecycle.site),
+    )
+}
+
+// The current stage of the sandbox object `operand` points to, if we are tracking it
+fn tracked_sandbox(state: &State<DefaultBackend>, operand: &Operand) -> Option<SandboxLifecycle> {
+    let addr = state.operand_to_bv(operand).ok()?;
+    if addr.get_width() != POINTER_BITS {
+        return None;
+    }
+    current_lifecycles(state)
+        .into_iter()
+        .find(|lifecycle| state.bvs_must_be_equal(&lifecycle.sandbox, &addr).unwrap())
+}
+
+// The host touching the memory of a sandbox that has been destroyed
+fn check_access(
+    state: &State<DefaultBackend>,
+    instr: &Instruction,
+    pointer: &Operand,
+) -> CheckResult {
+    // Most paths never destroy a sandbox, and finding the current stages takes solver queries
+    let any_destroyed = with_context(|ctx| {
+        ctx.sandbox_lifecycles
+            .visible(state)
+            .any(|lifecycle| lifecycle.stage == Lifecycle::Destroyed)
+    });
+    if !any_destroyed {
+        return Ok(());
+    }
+    let destroyed: Vec<SandboxLifecycle> = current_lifecycles(state)
+        .into_iter()
+        .filter(|lifecycle| lifecycle.stage == Lifecycle::Destroyed)
+        .collect();
+    let addr = state.operand_to_bv(pointer).unwrap();
+    for lifecycle in destroyed {
+        let Some(region) = region_of(state, &lifecycle.sandbox) else {
+            continue;
+        };
+        let inside = region.contains(&addr);
+        if state
+            .sat_with_extra_constraints(std::iter::once(&inside))
+            .unwrap()
+        {
+            return Err(Violation::new(
+                CheckErr::UseAfterDestroy,
+                CHECKER_ID,
+                &state.cur_loc.func.name,
+                instr,
+            )
+            .with_condition(inside)
+            .with_value("pointer", addr)
+            .with_related("sandbox destroyed", lifecycle.site));
+        }
+    }
+    Ok(())
+}
+
+// Sandboxes set up on a path that returned normally without destroying them. Only those
+// constructed on the path count: one that was merely created may belong to the caller, who
+// is then the one to destroy it.
+pub fn check_sandbox_leaks(trace: &ExecutionTrace<'_>) -> Vec<Violation> {
+    if trace.result.is_err() {
+        return Vec::new();
+    }
+    let state = &trace.state;
+    current_lifecycles(state)
+        .into_iter()
+        .filter(|lifecycle| lifecycle.stage == Lifecycle::Created)
+        .filter(|lifecycle| {
+            lifecycle_of(state, &lifecycle.sandbox)
+                .iter()
+                .any(|entry| entry.stage == Lifecycle::Constructed)
+        })
+        .map(|lifecycle| leak(state, lifecycle.sandbox, lifecycle.site))
+        .collect()
+}
+
+fn leak(state: &State<DefaultBackend>, sandbox: BV, created: Site) -> Violation {
+    let mut violation = Violation::new(
+        CheckErr::SandboxLeak,
+        CHECKER_ID,
+        &state.cur_loc.func.name,
+        current_instr(state),
+    )
+    .with_severity(Severity::Warning)
+    .with_value("sandbox", sandbox)
+    .with_related("sandbox created", created);
+    violation.locate(state, state.cur_loc.source_loc);
+    violation
+}
@@ -0,0 +1,160 @@
+use super::{CheckErr, CheckResult, Checker, ExecutionTrace, Severity, Violation};
+use crate::allocations::{AllocKind, Allocation, POINTER_BITS, allocation_containing};
+use crate::context::with_context;
+use crate::sandbox::{Lifecycle, current_lifecycles, freed_blocks, in_sandbox_memory};
+use crate::utils::current_instr;
+use haybale::backend::Backend;
+use haybale::backend::DefaultBackend;
+use haybale::{ExecutionManager, ReturnValue, State};
+use llvm_ir::Instruction;
+
+pub const CHECKER_ID: &str = "sandbox_memory_leak";
+
+type BV = <DefaultBackend as Backend>::BV;
+
+// Reports, on every path that returns normally, the `malloc_in_sandbox` blocks nobody can free
+// any more: never passed to `free_in_sandbox`, not returned, and with no pointer to them stored
+// anywhere but the stack. Blocks of a sandbox destroyed on the path are reclaimed along with it.
+pub struct SandboxMemoryLeakChecker;
+
+impl Checker for SandboxMemoryLeakChecker {
+    fn name(&self) -> &'static str {
+        CHECKER_ID
+    }
+
+    fn description(&self) -> &'static str {
+        "Block from malloc_in_sandbox that is never freed and can't be freed by anyone else"
+    }
+
+    fn monitor<'a>(
+        &self,
+        instr: &'a Instruction,
+        em: &ExecutionManager<'a, DefaultBackend>,
+    ) -> CheckResult {
+        if let Instruction::Store(store) = instr {
+            record_escaping_store(em.state(), &store.value, &store.address);
+        }
+        Ok(())
+    }
+
+    fn check_path(&self, trace: &ExecutionTrace<'_>) -> Vec<Violation> {
+        check_sandbox_memory_leaks(trace)
+    }
+}
+
+// Remember pointers into sandbox memory stored outside of any stack frame, where whoever
+// called us can still get at them
+fn record_escaping_store(
+    state: &State<DefaultBackend>,
+    value: &llvm_ir::Operand,
+    address: &llvm_ir::Operand,
+) {
+    let value = state.operand_to_bv(value).unwrap();
+    if value.get_width() != POINTER_BITS {
+        return;
+    }
+    let Some(inside) = in_sandbox_memory(state, &value) else {
+        return;
+    };
+    if !sat(state, &inside) {
+        return;
+    }
+    let address = state.operand_to_bv(address).unwrap();
+    let on_stack = allocation_containing(state, &address)
+        .is_some_and(|allocation| allocation.kind == AllocKind::Stack);
+    if !on_stack {
+        with_context(|ctx| ctx.escaped_sandbox_pointers.record(state, value));
+    }
+}
+
+pub fn check_sandbox_memory_leaks(trace: &ExecutionTrace<'_>) -> Vec<Violation> {
+    let returned = match &trace.result {
+        Ok(ReturnValue::Return(value)) => Some(value),
+        // The process is gone, along with the sandbox
+        Ok(ReturnValue::Abort) => return Vec::new(),
+        Ok(_) => None,
+        Err(_) => return Vec::new(),
+    };
+    let state = &trace.state;
+    let blocks: Vec<Allocation> = with_context(|ctx| {
+        ctx.allocations
+            .visible(state)
+            .filter(|allocation| allocation.kind == AllocKind::Sandbox)
+            .cloned()
+            .collect()
+    });
+    let freed = freed_blocks(state);
+    let escaped: Vec<BV> = with_context(|ctx| {
+        ctx.escaped_sandbox_pointers
+            .visible(state)
+            .cloned()
+            .chain(
+                returned
+                    .filter(|value| value.get_width() == POINTER_BITS)
+                    .cloned(),
+            )
+            .collect()
+    });
+    blocks
+        .into_iter()
+        .filter(|block| {
+            !freed.iter().any(|free| {
+                state
+                    .bvs_must_be_equal(&free.block.base, &block.base)
+                    .unwrap()
+            })
+        })
+        .filter(|block| {
+            !escaped
+                .iter()
+                .any(|pointer| points_into(state, block, pointer))
+        })
+        .filter(|block| !sandbox_destroyed(state, block))
+        .map(|block| leak(state, block))
+        .collect()
+}
+
+fn points_into(state: &State<DefaultBackend>, block: &Allocation, pointer: &BV) -> bool {
+    sat(
+        state,
+        &block.out_of_bounds(pointer, &state.one(POINTER_BITS)).not(),
+    )
+}
+
+// Whether the block belongs to a sandbox that was destroyed on this path
+fn sandbox_destroyed(state: &State<DefaultBackend>, block: &Allocation) -> bool {
+    let owners: Vec<BV> = with_context(|ctx| {
+        ctx.sandbox_regions
+            .visible(state)
+            .filter(|region| sat(state, &region.contains(&block.base)))
+            .map(|region| region.sandbox.clone())
+            .collect()
+    });
+    current_lifecycles(state).iter().any(|lifecycle| {
+        lifecycle.stage == Lifecycle::Destroyed
+            && owners
+                .iter()
+                .any(|owner| state.bvs_must_be_equal(owner, &lifecycle.sandbox).unwrap())
+    })
+}
+
+fn leak(state: &State<DefaultBackend>, block: Allocation) -> Violation {
+    let mut violation = Violation::new(
+        CheckErr::SandboxMemoryLeak,
+        CHECKER_ID,
+        &state.cur_loc.func.name,
+        current_instr(state),
+    )
+    .with_severity(Severity::Warning)
+    .with_value("pointer", block.base)
+    .with_value("size", block.size)
+    .with_related("allocated", block.site);
+    violation.locate(state, state.cur_loc.source_loc);
+    violation
+}
+
+fn sat(state: &State<DefaultBackend>, condition: &BV) -> bool {
+    state
+        .sat_with_extra_constraints(std::iter::once(condition))
+        .unwrap()
+}
@@ -0,0 +1,144 @@
+use super::{CheckErr, CheckResult, Checker, ExecutionTrace, Violation};
+use crate::context::with_context;
+use crate::sandbox::{SandboxString, in_sandbox_memory};
+use crate::taint::in_validator;
+use crate::utils::{current_instr, get_function_name};
+use haybale::backend::DefaultBackend;
+use haybale::{ExecutionManager, ReturnValue};
+use llvm_ir::{Instruction, Type};
+
+pub const CHECKER_ID: &str = "sandbox_string";
+
+// libc functions that read a string up to its terminator, with the arguments that are strings.
+// Formatted output is handled separately, as any of its arguments may be a string.
+const STRING_READERS: &[(&str, &[usize])] = &[
+    ("strlen", &[0]),
+    ("strcpy", &[1]),
+    ("strcat", &[0, 1]),
+    ("strdup", &[0]),
+    ("strcmp", &[0, 1]),
+    ("strchr", &[0]),
+    ("strstr", &[0, 1]),
+    ("atoi", &[0]),
+    ("puts", &[0]),
+    ("fputs", &[0]),
+];
+
+const FORMATTERS: &[&str] = &["printf", "fprintf", "sprintf", "snprintf"];
+
+// A string in sandbox memory can be made as long as the guest likes, or left unterminated, and
+// can change while the host reads it. The host should only ever read one through
+// `copy_and_verify_string`, which copies it out and hands the copy to a verifier that can bound
+// its length; the string functions that validator calls are fine. Once the verifier is done,
+// the copy should be no longer than the host allows and end inside the block the string
+// started in; a verifier that lets either slip is reported.
+pub struct SandboxStringChecker;
+
+impl Checker for SandboxStringChecker {
+    fn name(&self) -> &'static str {
+        CHECKER_ID
+    }
+
+    fn description(&self) -> &'static str {
+        "String in sandbox memory read by the host without going through copy_and_verify_string, or whose verifier doesn't bound it"
+    }
+
+    fn monitor<'a>(
+        &self,
+        instr: &'a Instruction,
+        em: &ExecutionManager<'a, DefaultBackend>,
+    ) -> CheckResult {
+        monitor_sandbox_string(instr, em)
+    }
+
+    fn check_path(&self, trace: &ExecutionTrace<'_>) -> Vec<Violation> {
+        check_verified_strings(trace)
+    }
+}
+
+pub fn monitor_sandbox_string<'a>(
+    instr: &'a Instruction,
+    em: &ExecutionManager<'a, DefaultBackend>,
+) -> CheckResult {
+    let Instruction::Call(call) = instr else {
+        return Ok(());
+    };
+    let Some(name) = get_function_name(call) else {
+        return Ok(());
+    };
+    let strings: Vec<usize> = match STRING_READERS.iter().find(|(reader, _)| *reader == name) {
+        Some((_, strings)) => strings.to_vec(),
+        None if FORMATTERS.contains(&name) => (0..call.arguments.len()).collect(),
+        None => return Ok(()),
+    };
+    let state = em.state();
+    for i in strings {
+        let Some((arg, _)) = call.arguments.get(i) else {
+            continue;
+        };
+        if !matches!(state.type_of(arg).as_ref(), Type::PointerType { .. }) {
+            continue;
+        }
+        let addr = state.operand_to_bv(arg).unwrap();
+        let Some(inside) = in_sandbox_memory(state, &addr) else {
+            return Ok(());
+        };
+        if !state
+            .sat_with_extra_constraints(std::iter::once(&inside))
+            .unwrap()
+        {
+            continue;
+        }
+        // Only looked up once we know there is something to report, as it walks the whole path
+        if in_validator(state) {
+            return Ok(());
+        }
+        return Err(Violation::new(
+            CheckErr::UnverifiedString,
+            CHECKER_ID,
+            &state.cur_loc.func.name,
+            instr,
+        )
+        .with_condition(inside)
+        .with_value("string", addr));
+    }
+    Ok(())
+}
+
+// Warn about every string copied out by `copy_and_verify_string` whose verifier left the length
+// as long as the guest likes, or let the string run past its block
+pub fn check_verified_strings(trace: &ExecutionTrace<'_>) -> Vec<Violation> {
+    let state = &trace.state;
+    // Where the verifier rejected the string, or the path ended before it was done, there is
+    // nothing it let through
+    if matches!(trace.result, Ok(ReturnValue::Abort)) || in_validator(state) {
+        return Vec::new();
+    }
+    let strings: Vec<SandboxString> =
+        with_context(|ctx| ctx.sandbox_strings.visible(state).cloned().collect());
+    strings
+        .into_iter()
+        .filter_map(|string| {
+            let unchecked = string.unchecked(state);
+            if !state
+                .sat_with_extra_constraints(std::iter::once(&unchecked))
+                .unwrap()
+            {
+                return None;
+            }
+            let mut violation = Violation::new(
+                CheckErr::UnverifiedString,
+                CHECKER_ID,
+                &state.cur_loc.func.name,
+                current_instr(state),
+            )
+            .with_condition(unchecked)
+            .with_value("length", string.len)
+            .with_related("copied", string.site);
+            // The string itself came straight from the sandbox
+            violation.sandbox_controlled = true;
+            violation.locate(state, state.cur_loc.source_loc);
+            Some(violation)
+        })
+        .collect()
+}
@@ -0,0 +1,201 @@
+use super::{CheckErr, CheckResult, Checker, Severity, Violation};
+use crate::taint::{in_validator, is_tainted};
+use crate::utils::get_function_name;
+use either::Either;
+use haybale::ExecutionManager;
+use haybale::State;
+use haybale::backend::DefaultBackend;
+use haybale::config::Demangling;
+use llvm_ir::instruction::{Call, ICmp};
+use llvm_ir::terminator::CondBr;
+use llvm_ir::{Function, Instruction, Name, Operand, Terminator};
+
+pub const CHECKER_ID: &str = "tainted_use";
+
+// Flags sandbox-controlled values reaching a sensitive sink without going through one of the
+// `copy_and_verify*` validators (other than inside one), whether or not the value can actually
+// do harm on this path. Those that can are also reported, as errors, by the checker guarding
+// the sink; what this one adds is the unchecked-but-currently-benign uses, which only stay
+// benign for as long as the sandbox keeps behaving. Findings are warnings and never end a path.
+pub struct TaintedUseChecker;
+
+impl Checker for TaintedUseChecker {
+    fn name(&self) -> &'static str {
+        CHECKER_ID
+    }
+
+    fn description(&self) -> &'static str {
+        "Sandbox-controlled value used as an index, pointer, size, length, loop bound or call target without being verified"
+    }
+
+    fn monitor<'a>(
+        &self,
+        instr: &'a Instruction,
+        em: &ExecutionManager<'a, DefaultBackend>,
+    ) -> CheckResult {
+        monitor_tainted_use(instr, em)
+    }
+}
+
+pub fn monitor_tainted_use<'a>(
+    instr: &'a Instruction,
+    em: &ExecutionManager<'a, DefaultBackend>,
+) -> CheckResult {
+    let state = em.state();
+    let Some((sink, operand)) = tainted_sink(state, instr) else {
+        return Ok(());
+    };
+    // Verifiers are where tainted values get checked, so they are bound to use them. Only
+    // looked up once there is a sink, as it walks the whole path
+    if in_validator(state) {
+        return Ok(());
+    }
+    let value = state.operand_to_bv(operand).unwrap();
+    Err(Violation::new(
+        CheckErr::UncheckedTaintedUse,
+        CHECKER_ID,
+        &state.cur_loc.func.name,
+        instr,
+    )
+    .with_severity(Severity::Warning)
+    .with_value(sink, value))
+}
+
+// The kind of sink `instr` is and the tainted operand reaching it, if any
+fn tainted_sink<'i>(
+    state: &State<DefaultBackend>,
+    instr: &'i Instruction,
+) -> Option<(&'static str, &'i Operand)> {
+    let tainted = |operand: &&Operand| is_tainted(state, operand);
+    match instr {
+        Instruction::GetElementPtr(gep) => gep.indices.iter().find(tainted).map(|i| ("index", i)),
+        Instruction::Load(load) => tainted_pointer(state, &load.address),
+        Instruction::Store(store) => tainted_pointer(state, &store.address),
+        Instruction::Alloca(alloca) => {
+            tainted(&&alloca.num_elements).then_some(("allocation size", &alloca.num_elements))
+        }
+        Instruction::Call(call) => tainted_call_sink(state, call),
+        Instruction::ICmp(icmp) if is_loop_condition(state.cur_loc.func, icmp) => {
+            [&icmp.operand0, &icmp.operand1]
+                .into_iter()
+                .find(tainted)
+                .map(|bound| ("loop bound", bound))
+        }
+        _ => None,
+    }
+}
+
+// A tainted address is only worth its own finding if the taint didn't come in through an
+// index, which was already flagged at the GEP computing the address
+fn tainted_pointer<'i>(
+    state: &State<DefaultBackend>,
+    address: &'i Operand,
+) -> Option<(&'static str, &'i Operand)> {
+    if !is_tainted(state, address) {
+        return None;
+    }
+    let indexed = match definition(state.cur_loc.func, address) {
+        Some(Instruction::GetElementPtr(gep)) => {
+            gep.indices.iter().any(|index| is_tainted(state, index))
+        }
+        _ => false,
+    };
+    (!indexed).then_some(("pointer", address))
+}
+
+fn tainted_call_sink<'i>(
+    state: &State<DefaultBackend>,
+    call: &'i Call,
+) -> Option<(&'static str, &'i Operand)> {
+    let tainted_arg = |i: usize| {
+        call.arguments
+            .get(i)
+            .map(|(arg, _)| arg)
+            .filter(|arg| is_tainted(state, arg))
+    };
+    let name = match &call.function {
+        Either::Right(target @ Operand::LocalOperand { .. }) => {
+            return is_tainted(state, target).then_some(("call target", target));
+        }
+        _ => get_function_name(call)?,
+    };
+    match name {
+        "malloc" | "_Znwm" | "_Znam" => tainted_arg(0).map(|size| ("allocation size", size)),
+        "calloc" => tainted_arg(0)
+            .or_else(|| tainted_arg(1))
+            .map(|size| ("allocation size", size)),
+        "realloc" => tainted_arg(1).map(|size| ("allocation size", size)),
+        "memcpy" | "memmove" | "memset" | "strncpy" => tainted_arg(2).map(|len| ("length", len)),
+        _ if name.starts_with("llvm.memcpy")
+            || name.starts_with("llvm.memmove")
+            || name.starts_with("llvm.memset") =>
+        {
+            tainted_arg(2).map(|len| ("length", len))
+        }
+        _ => {
+            let demangled = Demangling::autodetect(state.proj).maybe_demangle(name);
+            if demangled.contains("malloc_in_sandbox") {
+                // The element count, if this is the overload taking one
+                tainted_arg(1).map(|count| ("allocation size", count))
+            } else if demangled.contains("::operator[]") {
+                // Hooked indexing operators, where no GEP is left to flag; the first argument
+                // is `this`
+                (1..call.arguments.len())
+                    .find_map(tainted_arg)
+                    .map(|index| ("index", index))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+// The instruction defining `operand` in `func`, if it is a local
+fn definition<'f>(func: &'f Function, operand: &Operand) -> Option<&'f Instruction> {
+    let Operand::LocalOperand { name, .. } = operand else {
+        return None;
+    };
+    func.basic_blocks
+        .iter()
+        .flat_map(|bb| &bb.instrs)
+        .find(|instr| instr.try_get_result() == Some(name))
+}
+
+// Whether `icmp` decides whether a loop keeps going: it is what its block branches on, and
+// that block is a loop header. Without a dominator tree we call a block a loop header if a
+// block laid out after it branches back to it, which is how clang lays out loops.
+fn is_loop_condition(func: &Function, icmp: &ICmp) -> bool {
+    let Some(position) = func.basic_blocks.iter().position(|bb| {
+        bb.instrs
+            .iter()
+            .any(|instr| instr.try_get_result() == Some(&icmp.dest))
+    }) else {
+        return false;
+    };
+    let header = &func.basic_blocks[position];
+    let branches_on_icmp = matches!(
+        &header.term,
+        Terminator::CondBr(CondBr {
+            condition: Operand::LocalOperand { name, .. },
+            ..
+        }) if *name == icmp.dest
+    );
+    branches_on_icmp
+        && func.basic_blocks[position..]
+            .iter()
+            .any(|bb| successors(&bb.term).contains(&&header.name))
+}
+
+fn successors(term: &Terminator) -> Vec<&Name> {
+    match term {
+        Terminator::Br(br) => vec![&br.dest],
+        Terminator::CondBr(condbr) => vec![&condbr.true_dest, &condbr.false_dest],
+        Terminator::Switch(switch) => switch
+            .dests
+            .iter()
+            .map(|(_, dest)| dest)
+            .chain(std::iter::once(&switch.default_dest))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,117 @@
+use crate::allocations::{Allocation, PendingAllocation};
+use crate::checkers::Violation;
+use crate::checkers::double_fetch::DoubleFetch;
+use crate::checkers::oob::SubObject;
+use crate::counterexample::SandboxRead;
+use crate::sandbox::{
+    SandboxFetch, SandboxFree, SandboxLifecycle, SandboxMemoryModel, SandboxRegion, SandboxString,
+    StaleSandboxMemory,
+};
+use crate::taint::{MemoryTaint, ValueTaint};
+use haybale::State;
+use haybale::backend::Backend;
+use haybale::backend::DefaultBackend;
+use llvm_ir::BasicBlock;
+use std::cell::RefCell;
+
+// Function hooks are registered as plain fns, so anything they share with monitors and checkers
+// has to live outside of haybale's state. One context per thread keeps parallel tests apart.
+thread_local! {
+    static CONTEXT: RefCell<Context> = RefCell::default();
+}
+
+/// Analysis facts gathered during the current `symex_func_and_monitor` run
+#[derive(Default)]
+pub struct Context {
+    /// Violations reported by monitors
+    pub violations: PathLog<Violation>,
+    /// Names and symbolic values of the entry function's parameters
+    pub params: Vec<(String, <DefaultBackend as Backend>::BV)>,
+    /// Values the host got from the sandbox
+    pub sandbox_reads: PathLog<SandboxRead>,
+    /// Every block of memory the program may access
+    pub allocations: PathLog<Allocation>,
+    /// Struct fields strict bounds checking has seen pointers into
+    pub subobjects: PathLog<SubObject>,
+    /// When sandbox memory can change, from `ExecOptions`
+    pub sandbox_memory: SandboxMemoryModel,
+    /// Memory owned by each sandbox; a sandbox's latest entry is current
+    pub sandbox_regions: PathLog<SandboxRegion>,
+    /// Sandbox objects being constructed, created and destroyed; a sandbox's latest entry is
+    /// current
+    pub sandbox_lifecycles: PathLog<SandboxLifecycle>,
+    /// Sandbox blocks given back with `free_in_sandbox`
+    pub sandbox_frees: PathLog<SandboxFree>,
+    /// Pointers into sandbox memory stored somewhere other than the stack
+    pub escaped_sandbox_pointers: PathLog<<DefaultBackend as Backend>::BV>,
+    /// Parts of sandbox blocks too large to havoc, freshened on every read instead
+    pub stale_sandbox_memory: PathLog<StaleSandboxMemory>,
+    /// Sandbox strings `copy_and_verify_string` measured to copy them out
+    pub sandbox_strings: PathLog<SandboxString>,
+    /// Sandbox memory reads made through the accessors
+    pub sandbox_fetches: PathLog<SandboxFetch>,
+    /// Whether re-reading a sandbox location should be treated as a possible double fetch
+    pub track_double_fetches: bool,
+    /// Sandbox locations read twice
+    pub double_fetches: PathLog<DoubleFetch>,
+    /// Which SSA values the sandbox can influence
+    pub value_taint: PathLog<ValueTaint>,
+    /// Where tainted values were stored to memory
+    pub memory_taint: PathLog<MemoryTaint>,
+    /// Allocation made by the instruction that just executed, if any
+    pub pending_allocation: Option<PendingAllocation>,
+}
+
+/// Clear everything recorded by a previous run
+pub fn reset() {
+    CONTEXT.with(|ctx| *ctx.borrow_mut() = Context::default());
+}
+
+pub fn with_context<R>(f: impl FnOnce(&mut Context) -> R) -> R {
+    CONTEXT.with(|ctx| f(&mut ctx.borrow_mut()))
+}
+
+// A path is identified by the basic blocks it went through
+type PathKey = Vec<usize>;
+
+fn path_key(state: &State<DefaultBackend>) -> PathKey {
+    state
+        .get_path()
+        .iter()
+        .map(|entry| entry.0.bb as *const BasicBlock as usize)
+        .collect()
+}
+
+/// Facts recorded while exploring, each tied to the path it was recorded on.
+///
+/// Haybale rewinds its own state when it backtracks, but not ours, so a fact is only visible
+/// from paths that went through the point where it was recorded.
+pub struct PathLog<T> {
+    entries: Vec<(PathKey, T)>,
+}
+
+impl<T> Default for PathLog<T> {
+    fn default() -> Self {
+        PathLog {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<T> PathLog<T> {
+    pub fn record(&mut self, state: &State<DefaultBackend>, fact: T) {
+        self.entries.push((path_key(state), fact));
+    }
+
+    /// Facts recorded on the path `state` is on, oldest first
+    pub fn visible<'s>(
+        &'s self,
+        state: &State<DefaultBackend>,
+    ) -> impl Iterator<Item = &'s T> + 's {
+        let key = path_key(state);
+        self.entries
+            .iter()
+            .filter(move |(recorded, _)| key.starts_with(recorded))
+            .map(|(_, fact)| fact)
+    }
+}
@@ -0,0 +1,100 @@
+use crate::context::with_context;
+use haybale::State;
+use haybale::backend::Backend;
+use haybale::backend::DefaultBackend;
+use std::fmt;
+
+type BV = <DefaultBackend as Backend>::BV;
+
+/// A value the host got from the sandbox, by reading sandbox memory or as the result of a
+/// sandbox call. The sandbox fully controls it.
+#[derive(Debug, Clone)]
+pub struct SandboxRead {
+    /// Which accessor read it, and where
+    pub label: String,
+    pub value: BV,
+}
+
+/// Concrete values that drive a path into a violation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Counterexample {
+    /// Parameters of the entry function
+    pub params: Vec<(String, u64)>,
+    /// Values the host got from the sandbox along the path
+    pub sandbox_reads: Vec<(String, u64)>,
+    /// Values the checker singled out, e.g. the offending index or denominator
+    pub values: Vec<(String, u64)>,
+}
+
+impl Counterexample {
+    /// Ask the solver for a model of the current path where `condition` also holds.
+    /// Returns `None` if there is no such model.
+    pub fn solve(
+        state: &State<DefaultBackend>,
+        condition: Option<&BV>,
+        values: &[(String, BV)],
+    ) -> haybale::Result<Option<Self>> {
+        // Scope the condition, and the values pinned while solving, so the path itself stays
+        // unconstrained
+        state.solver.push(1);
+        if let Some(condition) = condition {
+            condition.assert();
+        }
+        let counterexample = Self::from_model(state, values);
+        state.solver.pop(1);
+        counterexample
+    }
+
+    fn from_model(
+        state: &State<DefaultBackend>,
+        values: &[(String, BV)],
+    ) -> haybale::Result<Option<Self>> {
+        if !state.sat()? {
+            return Ok(None);
+        }
+        let (params, sandbox_reads) = with_context(|ctx| {
+            let sandbox_reads: Vec<(String, BV)> = ctx
+                .sandbox_reads
+                .visible(state)
+                .map(|read| (read.label.clone(), read.value.clone()))
+                .collect();
+            (ctx.params.clone(), sandbox_reads)
+        });
+        Ok(Some(Counterexample {
+            params: solve_all(state, &params)?,
+            sandbox_reads: solve_all(state, &sandbox_reads)?,
+            values: solve_all(state, values)?,
+        }))
+    }
+}
+
+// Values too wide for a u64 (e.g. by-value structs) are left out. Each value is pinned once
+// solved, so that later ones come from the same model; the caller's solver scope drops the pins.
+fn solve_all(
+    state: &State<DefaultBackend>,
+    named: &[(String, BV)],
+) -> haybale::Result<Vec<(String, u64)>> {
+    let mut solved = Vec::new();
+    for (name, bv) in named {
+        if let Some(value) = state.get_a_solution_for_bv(bv)?.and_then(|s| s.as_u64()) {
+            bv._eq(&state.bv_from_u64(value, bv.get_width())).assert();
+            solved.push((name.clone(), value));
+        }
+    }
+    Ok(solved)
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.values {
+            writeln!(f, "{} = {}", name, value)?;
+        }
+        for (name, value) in &self.params {
+            writeln!(f, "param {} = {}", name, value)?;
+        }
+        for (label, value) in &self.sandbox_reads {
+            writeln!(f, "{} = {}", label, value)?;
+        }
+        Ok(())
+    }
+}
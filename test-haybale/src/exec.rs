@@ -1,39 +1,196 @@
-use crate::checkers::CheckResult;
+use crate::allocations::track_allocations;
+use crate::checkers::Checker;
 use crate::checkers::ExecutionTrace;
+use crate::checkers::Registry;
+use crate::checkers::Severity;
+use crate::checkers::TraceResult;
+use crate::checkers::Violation;
 use crate::checkers::check_trace;
-use crate::checkers::div_by_zero::monitor_div_by_zero;
-use crate::checkers::oob::monitor_oob;
+use crate::checkers::double_fetch;
+use crate::context::{self, with_context};
+use crate::counterexample::Counterexample;
 use crate::hooks::add_hooks;
-use haybale::{Config, Project, backend::DefaultBackend, symex_function};
-// TODO: get source names for reporting bugs.
-//  Example code that should help
-// let source_locs = path_entry.get_all_source_locs().collect::<Vec<_>>();
-// println!("source_locs: {:?}", source_locs);
+use crate::sandbox::SandboxMemoryModel;
+use crate::taint::{instr_is_tainted, track_taint};
+use haybale::{Config, Error, Project, backend::DefaultBackend, symex_function};
+use llvm_ir::HasDebugLoc;
+use std::rc::Rc;
+
+/// Knobs for a single symbolic execution run
+#[derive(Debug, Clone)]
+pub struct ExecOptions {
+    pub loop_bound: usize,
+    /// Keep exploring a path after a violation, assuming it did not happen, so that every
+    /// violation on the path gets reported instead of just the first one
+    pub exhaustive: bool,
+    /// Checkers to run
+    pub checkers: Registry,
+    /// When the guest can change sandbox memory
+    pub sandbox_memory: SandboxMemoryModel,
+}
+
+impl Default for ExecOptions {
+    fn default() -> Self {
+        ExecOptions {
+            loop_bound: 1000,
+            exhaustive: false,
+            checkers: Registry::default(),
+            sandbox_memory: SandboxMemoryModel::default(),
+        }
+    }
+}
+
+// Monitors record their typed violation in the context; haybale only sees an opaque error,
+// which ends the path. In exhaustive mode we instead assume the violation away and carry on.
+// Warnings are recorded without ending anything.
+fn add_monitor(config: &mut Config<DefaultBackend>, exhaustive: bool, checker: Rc<dyn Checker>) {
+    config
+        .callbacks
+        .add_instruction_callback(move |instr, em| match checker.monitor(instr, em) {
+            Ok(()) => Ok(()),
+            Err(violation) => {
+                let state = em.state();
+                let mut violation = double_fetch::explain(state, &violation)?.unwrap_or(violation);
+                violation.sandbox_controlled |= instr_is_tainted(state, instr);
+                violation.locate(state, instr.get_debug_loc().as_ref());
+                violation.counterexample =
+                    Counterexample::solve(state, violation.condition.as_ref(), &violation.values)?;
+                let err = Error::OtherError(violation.to_string());
+                let condition = violation.condition.clone();
+                let severity = violation.severity;
+                with_context(|ctx| ctx.violations.record(state, violation));
+                if severity == Severity::Warning {
+                    return Ok(());
+                }
+                match condition {
+                    Some(condition) if exhaustive => {
+                        condition.not().assert();
+                        // If the violation was unavoidable there is nothing left to explore
+                        if state.sat()? { Ok(()) } else { Err(err) }
+                    }
+                    _ => Err(err),
+                }
+            }
+        });
+}
 
 pub fn symex_func_and_monitor<'a>(
     func_name: &str,
     project: &'a Project,
-    loop_bound: usize,
+    options: &ExecOptions,
 ) -> Vec<ExecutionTrace<'a>> {
+    context::reset();
+    with_context(|ctx| ctx.sandbox_memory = options.sandbox_memory);
     let mut config: Config<DefaultBackend> = Config::default();
     add_hooks(&mut config);
+    // Registered first, so monitors see the allocations made so far and the taint of the
+    // operands they are looking at, whichever checkers are enabled
+    config.callbacks.add_instruction_callback(track_allocations);
+    config.callbacks.add_instruction_callback(track_taint);
 
-    config
-        .callbacks
-        .add_instruction_callback(monitor_div_by_zero);
-    config.callbacks.add_instruction_callback(monitor_oob);
+    for checker in options.checkers.enabled() {
+        checker.prepare();
+        add_monitor(&mut config, options.exhaustive, Rc::clone(checker));
+    }
 
-    config.loop_bound = loop_bound;
+    config.loop_bound = options.loop_bound;
 
     let mut em = symex_function(func_name, project, config, None).unwrap();
+    let params = em
+        .func()
+        .parameters
+        .iter()
+        .map(|param| param.name.to_string())
+        .zip(em.param_bvs().iter().cloned())
+        .collect();
+    with_context(|ctx| ctx.params = params);
+
     let mut results: Vec<ExecutionTrace> = Vec::new();
     while let Some(path) = em.next() {
-        results.push((path.clone(), em.state().clone()));
+        let state = em.state().clone();
+        let violations = with_context(|ctx| ctx.violations.visible(&state).cloned().collect());
+        results.push(ExecutionTrace {
+            result: path,
+            state,
+            violations,
+        });
     }
     results
 }
 
-pub fn symex_and_check(func_name: &str, project: &Project, loop_bound: usize) -> Vec<CheckResult> {
-    let traces = symex_func_and_monitor(func_name, project, loop_bound);
-    traces.iter().map(|trace| check_trace(trace)).collect()
+/// How exploration of a path ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathStatus {
+    /// The path returned normally
+    Completed,
+    /// The path was cut short by a violation
+    Violation,
+    /// The path was abandoned after hitting the loop bound
+    LoopBound,
+    /// Haybale could not execute the path
+    Error(String),
+}
+
+/// The verdict on one path, and how exploring it ended
+#[derive(Debug)]
+pub struct CheckedPath {
+    pub status: PathStatus,
+    pub result: TraceResult,
+    /// Warnings reported along the path, which don't count towards `result`
+    pub warnings: Vec<Violation>,
+}
+
+// A monitor ends a path with an error carrying the text of the violation it recorded; any
+// other error is haybale's own, even if a checker found something on the path as well
+fn path_status(trace: &ExecutionTrace<'_>) -> PathStatus {
+    match &trace.result {
+        Ok(_) => PathStatus::Completed,
+        Err(Error::LoopBoundExceeded(_)) => PathStatus::LoopBound,
+        Err(Error::OtherError(message))
+            if trace.violations.iter().any(|violation| {
+                violation.severity == Severity::Error && violation.to_string() == *message
+            }) =>
+        {
+            PathStatus::Violation
+        }
+        Err(err) => PathStatus::Error(err.to_string()),
+    }
+}
+
+pub fn symex_and_check_paths(
+    func_name: &str,
+    project: &Project,
+    options: &ExecOptions,
+) -> Vec<CheckedPath> {
+    let traces = symex_func_and_monitor(func_name, project, options);
+    traces
+        .iter()
+        .map(|trace| {
+            let (result, warnings) = check_trace(trace, &options.checkers);
+            CheckedPath {
+                status: path_status(trace),
+                result,
+                warnings,
+            }
+        })
+        .collect()
+}
+
+pub fn symex_and_check_with(
+    func_name: &str,
+    project: &Project,
+    options: &ExecOptions,
+) -> Vec<TraceResult> {
+    symex_and_check_paths(func_name, project, options)
+        .into_iter()
+        .map(|path| path.result)
+        .collect()
+}
+
+pub fn symex_and_check(func_name: &str, project: &Project, loop_bound: usize) -> Vec<TraceResult> {
+    let options = ExecOptions {
+        loop_bound,
+        ..ExecOptions::default()
+    };
+    symex_and_check_with(func_name, project, &options)
 }
@@ -1,35 +1,214 @@
+use crate::allocations::{POINTER_BITS, to_pointer_width};
+use crate::context::with_context;
+use crate::counterexample::SandboxRead;
+use crate::location::SourceLoc;
+use crate::sandbox::{
+    HOST_STRING_LIMIT, Lifecycle, create_region, free_in_sandbox, grant_range,
+    havoc_sandbox_memory, in_sandbox_memory, malloc_in_sandbox, read_sandbox_memory,
+    record_granted_range, record_lifecycle, sandbox_strlen, tainted_pointer_arg,
+};
+use crate::taint::{in_validator, mark_call_result, taint_memory};
 use crate::utils::*;
 use haybale::backend::Backend;
 use haybale::function_hooks::generic_stub_hook;
 use haybale::{Config, Error, ReturnValue, State, backend::DefaultBackend, function_hooks::IsCall};
-use llvm_ir::TypeRef;
+use llvm_ir::function::ParameterAttribute;
+use llvm_ir::{Name, Type, TypeRef};
 
 // Type alias for cleaner function signatures
 type HookResult = Result<ReturnValue<<DefaultBackend as Backend>::BV>, Error>;
 
+// Past this many bytes, the length of a host string is left unconstrained. A verifier's strlen
+// on its copy of a sandbox string has to see as far as the host limit to bound the original.
+const HOST_STRLEN_BOUND: u64 = HOST_STRING_LIMIT;
+
 fn get_function_return_type(state: &mut State<DefaultBackend>, call: &dyn IsCall) -> TypeRef {
     let func_name = get_function_name(call).unwrap();
     let (func_def, _) = state.proj.get_func_by_name(func_name).unwrap();
     func_def.return_type.clone()
 }
 
-// TODO: make generic for any type (currently hardcoded to int)
-fn unsafe_unverified_hook(state: &mut State<DefaultBackend>, call: &dyn IsCall) -> HookResult {
-    let call_args = get_args_exact(call, 1)?;
+/// Where the result of a hooked call goes
+enum CallResult {
+    Void,
+    /// Returned as a value of this type
+    Value(TypeRef),
+    /// Written through the pointer passed as first argument, as is done for aggregates too
+    /// large to return in registers
+    Sret {
+        addr: <DefaultBackend as Backend>::BV,
+        ty: TypeRef,
+    },
+}
+
+impl CallResult {
+    fn ty(&self) -> Option<&TypeRef> {
+        match self {
+            CallResult::Void => None,
+            CallResult::Value(ty) | CallResult::Sret { ty, .. } => Some(ty),
+        }
+    }
+}
+
+fn get_call_result(
+    state: &mut State<DefaultBackend>,
+    call: &dyn IsCall,
+) -> Result<CallResult, Error> {
+    let return_type = get_function_return_type(state, call);
+    if !matches!(return_type.as_ref(), Type::VoidType) {
+        return Ok(CallResult::Value(return_type));
+    }
+    let func_name = get_function_name(call).unwrap();
+    let (func_def, _) = state.proj.get_func_by_name(func_name).unwrap();
+    let sret = func_def.parameters.first().filter(|param| {
+        param
+            .attributes
+            .iter()
+            .any(|attr| matches!(attr, ParameterAttribute::SRet { .. }))
+    });
+    match (sret, call.get_arguments().first()) {
+        (Some(param), Some((arg, _))) => Ok(CallResult::Sret {
+            addr: get_operand(state, arg)?,
+            ty: get_pointer_type(&param.ty),
+        }),
+        _ => Ok(CallResult::Void),
+    }
+}
+
+/// The arguments of a hooked call, leaving out the sret pointer if there is one
+fn get_own_args_exact<'c>(
+    call: &'c dyn IsCall,
+    result: &CallResult,
+    expected_count: usize,
+) -> Result<Vec<&'c llvm_ir::Operand>, Error> {
+    let skip = usize::from(matches!(result, CallResult::Sret { .. }));
+    let call_args: Vec<_> = call
+        .get_arguments()
+        .iter()
+        .skip(skip)
+        .map(|(arg, _)| arg)
+        .collect();
+    if call_args.len() != expected_count {
+        return Err(Error::OtherError(format!(
+            "Expected {} arguments, got {}",
+            expected_count,
+            call_args.len()
+        )));
+    }
+    Ok(call_args)
+}
+
+/// Hand `value` back as the result of the call, marking it as coming from the sandbox
+fn return_tainted(
+    state: &mut State<DefaultBackend>,
+    result: CallResult,
+    value: <DefaultBackend as Backend>::BV,
+) -> HookResult {
+    match result {
+        CallResult::Void => Ok(ReturnValue::ReturnVoid),
+        CallResult::Value(_) => {
+            mark_call_result(state, true);
+            Ok(ReturnValue::Return(value))
+        }
+        CallResult::Sret { addr, .. } => {
+            taint_memory(state, &addr, value.get_width().div_ceil(8));
+            state.write(&addr, value)?;
+            Ok(ReturnValue::ReturnVoid)
+        }
+    }
+}
+
+// Remember a value read out of sandbox memory, so counterexamples can show what the sandbox sent
+fn record_sandbox_read(
+    state: &State<DefaultBackend>,
+    accessor: &str,
+    value: &<DefaultBackend as Backend>::BV,
+) {
+    let label = match state.cur_loc.source_loc {
+        Some(loc) => format!("{}() at {}", accessor, SourceLoc::from(loc)),
+        None => format!("{}() in {}", accessor, state.cur_loc.func.name),
+    };
+    let read = SandboxRead {
+        label,
+        value: value.clone(),
+    };
+    with_context(|ctx| ctx.sandbox_reads.record(state, read));
+}
+
+// Read the value a tainted object wraps, of whatever type the accessor returns
+fn read_tainted_value(
+    state: &mut State<DefaultBackend>,
+    call: &dyn IsCall,
+    accessor: &str,
+) -> HookResult {
+    let result = get_call_result(state, call)?;
+    let call_args = get_own_args_exact(call, &result, 1)?;
     let tainted_bv = get_operand(state, call_args[0])?;
-    // Read the actual integer value from the tainted_volatile object
-    let value_bv = state.read(&tainted_bv, 32)?;
+    let Some(ty) = result.ty() else {
+        return Ok(ReturnValue::ReturnVoid);
+    };
+    let bits = state.size_in_bits(ty).unwrap();
+    // Memory holds whole bytes: a bool, say, is stored as a byte but returned as an i1
+    let stored_bv = read_sandbox_memory(state, &tainted_bv, bits.div_ceil(8) * 8)?;
+    let value_bv = if stored_bv.get_width() > bits {
+        stored_bv.slice(bits - 1, 0)
+    } else {
+        stored_bv
+    };
+    record_sandbox_read(state, accessor, &value_bv);
+    return_tainted(state, result, value_bv)
+}
 
-    Ok(ReturnValue::Return(value_bv))
+fn unsafe_unverified_hook(state: &mut State<DefaultBackend>, call: &dyn IsCall) -> HookResult {
+    read_tainted_value(state, call, "UNSAFE_unverified")
+}
+
+/// HOOKED_ON: rlbox::tainted_base_impl<rlbox::tainted, char*, rlbox::rlbox_noop_sandbox>::unverified_safe_pointer_because
+// The host vouches for the pointer being good for `count` elements, so we record that range
+// for the host's accesses to be checked against. Unlike RLBox, we don't assume the range is in
+// sandbox memory: that the pointer can leave it is exactly what the sandbox escape checker is
+// after.
+fn unverified_safe_pointer_because_hook(
+    state: &mut State<DefaultBackend>,
+    call: &dyn IsCall,
+) -> HookResult {
+    let result = get_call_result(state, call)?;
+    // The last argument is the reason, which is only there for reviewers
+    let call_args = get_own_args_exact(call, &result, 3)?;
+    let tainted_bv = get_operand(state, call_args[0])?;
+    let count_bv = get_operand(state, call_args[1])?;
+    let Some(pointer_ty) = result.ty() else {
+        return Ok(ReturnValue::ReturnVoid);
+    };
+    let element_bytes = state
+        .size_in_bits(&get_pointer_type(pointer_ty))
+        .map_or(1, |bits| bits.div_ceil(8));
+    let pointer_bv = read_sandbox_memory(state, &tainted_bv, POINTER_BITS)?;
+    record_sandbox_read(state, "unverified_safe_pointer_because", &pointer_bv);
+    let size_bv = state
+        .bv_from_u32(element_bytes, POINTER_BITS)
+        .mul(&to_pointer_width(&count_bv));
+    record_granted_range(state, &pointer_bv, &size_bv);
+    return_tainted(state, result, pointer_bv)
 }
 
 // assignment where lhs is tainted.
-// what are the two arguments here?
+// the arguments are `this` and the rhs, which is passed by (forwarding) reference
 fn rlbox_assign_hook(state: &mut State<DefaultBackend>, call: &dyn IsCall) -> HookResult {
     let call_args = get_args_exact(call, 2)?;
-    let value_bv = get_operand(state, call_args[1])?;
-
-    Ok(ReturnValue::Return(value_bv)) // I think we just return rhs?
+    let lhs_bv = get_operand(state, call_args[0])?;
+    let rhs_bv = get_operand(state, call_args[1])?;
+    let value_bv = match get_operand_type(state, call_args[1]).as_ref() {
+        Type::PointerType { pointee_type, .. } => {
+            let width = state.size_in_bits(pointee_type).unwrap();
+            state.read(&rhs_bv, width)?
+        }
+        _ => rhs_bv,
+    };
+    // The write has to really happen, so that it can be undone when the sandbox next runs
+    state.write(&lhs_bv, value_bv)?;
+
+    Ok(ReturnValue::Return(lhs_bv))
 }
 
 // returns a reference to the pointed-at element
@@ -115,33 +294,177 @@ fn rlbox_deref_hook(state: &mut State<DefaultBackend>, call: &dyn IsCall) -> Hoo
 }
 
 /// HOOKED_ON: rlbox::rlbox_sandbox<rlbox::rlbox_test_sandbox>::malloc_in_sandbox<int [4]>
+// Also the overload taking an element count
 fn malloc_in_sandbox_hook(state: &mut State<DefaultBackend>, call: &dyn IsCall) -> HookResult {
-    let _call_args = get_args_exact(call, 1)?;
+    let call_args: Vec<_> = call.get_arguments().iter().map(|(arg, _)| arg).collect();
+    if call_args.is_empty() || call_args.len() > 2 {
+        return Err(Error::OtherError(format!(
+            "Expected 1 or 2 arguments, got {}",
+            call_args.len()
+        )));
+    }
+    let sandbox_bv = get_operand(state, call_args[0])?;
     let return_type = get_function_return_type(state, call);
-    let get_pointer_type = get_pointer_type(&return_type);
-    let array_size_bits = state.size_in_bits(&get_pointer_type).unwrap();
-    let concrete_array_ptr = state.allocate(array_size_bits);
-    Ok(ReturnValue::Return(concrete_array_ptr))
-}
-
-// Wip
-// TODO: make generic for any return type (currently hardcoded to int)
-// TODO: figure out how to process the lambda?
-// The issue here is that the lambda is not actually an argument, but has already been monomorphized out so that there is a monomorphic `copy_and_verify` that we call
-// Solution: we should just not instrument copy_and_verify and let it do its own thing
-// fn copy_and_verify_hook(state: &mut State<DefaultBackend>, call: &dyn IsCall) -> HookResult {
-//     let args = get_args_exact(call, 1)?;
-//     println!("==> ARGS: {:?}", args[0]);
-//     let called_func = call.get_called_func();
-//     println!("==> CALLED_FUNC: {:#?}", called_func);
-
-//     // let tainted_bv = get_operand(state, args[0])?;
-//     // let value_bv = state.read(&tainted_bv, 32)?;
-//     // println!("==> ARGS: {:?}", args[0]);
-//     unimplemented!()
-
-//     // Ok(ReturnValue::Return(value_bv))
-// }
+    let element_ty = get_pointer_type(&return_type);
+    let element_bytes = state.size_in_bits(&element_ty).unwrap() / 8;
+    let mut size = state.bv_from_u32(element_bytes, POINTER_BITS);
+    if let Some(count) = call_args.get(1) {
+        let count_bv = get_operand(state, count)?;
+        size = size.mul(&to_pointer_width(&count_bv));
+    }
+    let array_ptr = malloc_in_sandbox(state, sandbox_bv, &size)?;
+    Ok(ReturnValue::Return(array_ptr))
+}
+
+/// HOOKED_ON: rlbox::rlbox_sandbox<rlbox::rlbox_noop_sandbox>::free_in_sandbox<char>
+// Bad frees are reported by the sandbox free checker, before we get here
+fn free_in_sandbox_hook(state: &mut State<DefaultBackend>, call: &dyn IsCall) -> HookResult {
+    let call_args = get_args_exact(call, 2)?;
+    let pointer_bv = tainted_pointer_arg(state, call_args[1])?;
+    free_in_sandbox(state, &pointer_bv);
+    Ok(ReturnValue::ReturnVoid)
+}
+
+// The sandbox object a method of `rlbox_sandbox` is called on
+fn get_sandbox(
+    state: &mut State<DefaultBackend>,
+    call: &dyn IsCall,
+) -> Result<<DefaultBackend as Backend>::BV, Error> {
+    let Some((sandbox, _)) = call.get_arguments().first() else {
+        return Err(Error::OtherError(
+            "Expected the sandbox as first argument".into(),
+        ));
+    };
+    get_operand(state, sandbox)
+}
+
+/// HOOKED_ON: rlbox::rlbox_sandbox<rlbox::rlbox_noop_sandbox>::rlbox_sandbox
+fn sandbox_constructor_hook(state: &mut State<DefaultBackend>, call: &dyn IsCall) -> HookResult {
+    let sandbox_bv = get_sandbox(state, call)?;
+    record_lifecycle(state, sandbox_bv, Lifecycle::Constructed);
+    generic_stub_hook(state, call)
+}
+
+/// HOOKED_ON: rlbox::rlbox_sandbox<rlbox::rlbox_noop_sandbox>::create_sandbox<>
+fn create_sandbox_hook(state: &mut State<DefaultBackend>, call: &dyn IsCall) -> HookResult {
+    let sandbox_bv = get_sandbox(state, call)?;
+    create_region(state, sandbox_bv.clone());
+    record_lifecycle(state, sandbox_bv, Lifecycle::Created);
+    generic_stub_hook(state, call)
+}
+
+/// HOOKED_ON: rlbox::rlbox_sandbox<rlbox::rlbox_noop_sandbox>::destroy_sandbox
+// The sandbox's memory stays where it was, so that accesses to it after this can be caught
+fn destroy_sandbox_hook(state: &mut State<DefaultBackend>, call: &dyn IsCall) -> HookResult {
+    let sandbox_bv = get_sandbox(state, call)?;
+    record_lifecycle(state, sandbox_bv, Lifecycle::Destroyed);
+    generic_stub_hook(state, call)
+}
+
+/// HOOKED_ON: rlbox::rlbox_sandbox<rlbox::rlbox_noop_sandbox>::INTERNAL_invoke_with_func_ptr<unsigned int (unsigned int, unsigned int), int, int>
+// Guest code is untrusted, so instead of running it we assume the worst: it returns anything
+// at all and overwrites everything it owns
+fn invoke_sandbox_function_hook(
+    state: &mut State<DefaultBackend>,
+    call: &dyn IsCall,
+) -> HookResult {
+    havoc_sandbox_memory(state)?;
+    let result = get_call_result(state, call)?;
+    let Some(ty) = result.ty() else {
+        return Ok(ReturnValue::ReturnVoid);
+    };
+    let width = state.size_in_bits(ty).unwrap();
+    let value_bv = state.new_bv_with_name(Name::from("invoke_sandbox_function"), width)?;
+    record_sandbox_read(state, "invoke_sandbox_function", &value_bv);
+    return_tainted(state, result, value_bv)
+}
+
+// The read at the heart of `copy_and_verify` and friends, which then hand the value to the
+// verifier lambda. We let those run as they are, so that the verifier's own checks constrain
+// what comes back; only the read itself goes through our model of sandbox memory.
+fn get_raw_value_hook(state: &mut State<DefaultBackend>, call: &dyn IsCall) -> HookResult {
+    read_tainted_value(state, call, "get_raw_value")
+}
+
+/// HOOKED_ON: rlbox::detail::check_range_doesnt_cross_app_sbx_boundary<rlbox::rlbox_noop_sandbox>
+// Called by copy_and_verify_range and copy_and_verify_buffer_address before they touch the
+// range. The noop sandbox's version knows nothing of our sandbox regions, so we check the range
+// against those instead; the pointer is then only good for that range.
+fn sandbox_range_hook(state: &mut State<DefaultBackend>, call: &dyn IsCall) -> HookResult {
+    let call_args = get_args_exact(call, 2)?;
+    let start_bv = get_operand(state, call_args[0])?;
+    let size_bv = get_operand(state, call_args[1])?;
+    grant_range(state, &start_bv, &size_bv)?;
+    Ok(ReturnValue::ReturnVoid)
+}
+
+// strlen is only declared, so haybale has nothing to run. Sandbox strings are as long as the
+// guest likes; host strings are scanned for their terminator.
+fn strlen_hook(state: &mut State<DefaultBackend>, call: &dyn IsCall) -> HookResult {
+    let call_args = get_args_exact(call, 1)?;
+    let str_bv = get_operand(state, call_args[0])?;
+    let in_sandbox = match in_sandbox_memory(state, &str_bv) {
+        Some(inside) => state.sat_with_extra_constraints(std::iter::once(&inside))?,
+        None => false,
+    };
+    if !in_sandbox {
+        return Ok(ReturnValue::Return(host_strlen(state, &str_bv)?));
+    }
+    if in_validator(state) {
+        return copy_and_verify_string_hook(state, &str_bv);
+    }
+    let string = sandbox_strlen(state, &str_bv, HOST_STRLEN_BOUND)?;
+    record_sandbox_read(state, "strlen", &string.len);
+    mark_call_result(state, true);
+    Ok(ReturnValue::Return(string.len))
+}
+
+// The strlen at the heart of `copy_and_verify_string`, which sizes the copy it hands to the
+// verifier. Like `copy_and_verify`, it runs as compiled, verifier included; we only remember
+// the string it measures, so that once the verifier is done we can tell whether it bounded
+// the length and made sure the string ends inside its block.
+fn copy_and_verify_string_hook(
+    state: &mut State<DefaultBackend>,
+    str_bv: &<DefaultBackend as Backend>::BV,
+) -> HookResult {
+    let string = sandbox_strlen(state, str_bv, HOST_STRLEN_BOUND)?;
+    record_sandbox_read(state, "copy_and_verify_string", &string.len);
+    mark_call_result(state, true);
+    let len_bv = string.len.clone();
+    with_context(|ctx| ctx.sandbox_strings.record(state, string));
+    Ok(ReturnValue::Return(len_bv))
+}
+
+// The index of the first zero byte, looking no further than the first byte that must be zero
+fn host_strlen(
+    state: &mut State<DefaultBackend>,
+    str_bv: &<DefaultBackend as Backend>::BV,
+) -> Result<<DefaultBackend as Backend>::BV, Error> {
+    let zero = state.zero(8);
+    let mut terminators = Vec::new();
+    let mut terminated = false;
+    for i in 0..HOST_STRLEN_BOUND {
+        let addr = str_bv.add(&state.bv_from_u64(i, str_bv.get_width()));
+        let is_zero = state.read(&addr, 8)?._eq(&zero);
+        terminated = !state.sat_with_extra_constraints(std::iter::once(&is_zero.not()))?;
+        if terminated {
+            break;
+        }
+        terminators.push(is_zero);
+    }
+    let mut len = if terminated {
+        state.bv_from_u64(terminators.len() as u64, POINTER_BITS)
+    } else {
+        let len = state.new_bv_with_name(Name::from("strlen"), POINTER_BITS)?;
+        len.ugte(&state.bv_from_u64(HOST_STRLEN_BOUND, POINTER_BITS))
+            .assert();
+        len
+    };
+    for (i, is_zero) in terminators.iter().enumerate().rev() {
+        len = is_zero.cond_bv(&state.bv_from_u64(i as u64, POINTER_BITS), &len);
+    }
+    Ok(len)
+}
 
 // fn instruction_callback(
 //     instr: &Instruction,
@@ -163,6 +486,10 @@ pub fn add_hooks(config: &mut Config<DefaultBackend>) {
     config
         .function_hooks
         .add_cpp_notemplate("rlbox::tainted_volatile::operator=", &rlbox_assign_hook);
+    config.function_hooks.add_cpp_notemplate(
+        "rlbox::tainted_base_impl::unverified_safe_pointer_because",
+        &unverified_safe_pointer_because_hook,
+    );
     config
         .function_hooks
         .add_cpp_notemplate("rlbox::tainted_base_impl::operator[]", &rlbox_index_hook);
@@ -176,19 +503,51 @@ pub fn add_hooks(config: &mut Config<DefaultBackend>) {
         "rlbox::rlbox_sandbox::malloc_in_sandbox",
         &malloc_in_sandbox_hook,
     );
+    config.function_hooks.add_cpp_notemplate(
+        "rlbox::rlbox_sandbox::free_in_sandbox",
+        &free_in_sandbox_hook,
+    );
 
+    // invoke_sandbox_function expands to one of these, depending on how symbols are looked up
     config.function_hooks.add_cpp_notemplate(
-        "rlbox::tainted_base_impl::copy_and_verify",
-        &generic_stub_hook,
+        "rlbox::rlbox_sandbox::INTERNAL_invoke_with_func_ptr",
+        &invoke_sandbox_function_hook,
+    );
+    config.function_hooks.add_cpp_notemplate(
+        "rlbox::rlbox_sandbox::INTERNAL_invoke_with_func_name",
+        &invoke_sandbox_function_hook,
     );
 
+    // copy_and_verify itself runs as compiled, verifier included
     config
         .function_hooks
-        .add_cpp_notemplate("rlbox::rlbox_sandbox::rlbox_sandbox", &generic_stub_hook);
+        .add_cpp_notemplate("rlbox::tainted::get_raw_value", &get_raw_value_hook);
+    config.function_hooks.add_cpp_notemplate(
+        "rlbox::tainted_volatile::get_raw_value",
+        &get_raw_value_hook,
+    );
+
+    // copy_and_verify_range and copy_and_verify_buffer_address run as compiled too, around
+    // our model of RLBox's range check
+    config.function_hooks.add_cpp_notemplate(
+        "rlbox::detail::check_range_doesnt_cross_app_sbx_boundary",
+        &sandbox_range_hook,
+    );
+
+    config.function_hooks.add("strlen", &strlen_hook);
+
+    config.function_hooks.add_cpp_notemplate(
+        "rlbox::rlbox_sandbox::rlbox_sandbox",
+        &sandbox_constructor_hook,
+    );
 
     config
         .function_hooks
-        .add_cpp_notemplate("rlbox::rlbox_sandbox::create_sandbox", &generic_stub_hook);
+        .add_cpp_notemplate("rlbox::rlbox_sandbox::create_sandbox", &create_sandbox_hook);
+    config.function_hooks.add_cpp_notemplate(
+        "rlbox::rlbox_sandbox::destroy_sandbox",
+        &destroy_sandbox_hook,
+    );
 
     // config.function_hooks.add_uc_hook(&default_uc_hook);
 }
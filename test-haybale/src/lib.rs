@@ -1,4 +1,11 @@
+pub mod allocations;
 pub mod checkers;
+pub mod context;
+pub mod counterexample;
 pub mod exec;
 pub mod hooks;
+pub mod location;
+pub mod report;
+pub mod sandbox;
+pub mod taint;
 pub mod utils;
@@ -0,0 +1,169 @@
+use crate::utils::current_instr;
+use haybale::BBInstrIndex;
+use haybale::State;
+use haybale::backend::DefaultBackend;
+use haybale::config::Demangling;
+use llvm_ir::{DebugLoc, Function, HasDebugLoc, Instruction};
+use std::fmt;
+
+/// A position in the analyzed program's source, from its debug info
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceLoc {
+    pub file: String,
+    pub directory: Option<String>,
+    pub line: u32,
+    pub col: Option<u32>,
+}
+
+impl From<&DebugLoc> for SourceLoc {
+    fn from(loc: &DebugLoc) -> Self {
+        SourceLoc {
+            file: loc.filename.clone(),
+            directory: loc.directory.clone(),
+            line: loc.line,
+            col: loc.col,
+        }
+    }
+}
+
+impl fmt::Display for SourceLoc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        if let Some(col) = self.col {
+            write!(f, ":{}", col)?;
+        }
+        Ok(())
+    }
+}
+
+/// One function on the call stack, with the source location it was executing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Demangled function name
+    pub func: String,
+    pub loc: Option<SourceLoc>,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.loc {
+            Some(loc) => write!(f, "{} ({})", self.func, loc),
+            None => write!(f, "{}", self.func),
+        }
+    }
+}
+
+/// A program point some fact was recorded at, e.g. where an allocation was made
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Site {
+    pub func: String,
+    pub instr: String,
+    pub loc: Option<SourceLoc>,
+}
+
+impl Site {
+    /// The instruction the state is currently at
+    pub fn current(state: &State<DefaultBackend>) -> Self {
+        Site {
+            func: state.cur_loc.func.name.clone(),
+            instr: current_instr(state),
+            loc: state.cur_loc.source_loc.map(SourceLoc::from),
+        }
+    }
+}
+
+impl fmt::Display for Site {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.loc {
+            Some(loc) => write!(f, "{} ({})", self.func, loc),
+            None => write!(f, "{}: {}", self.func, self.instr),
+        }
+    }
+}
+
+// Where a path entry left its function: the first call it made, or just where it started
+fn entry_loc(loc: &haybale::Location<'_>) -> Option<SourceLoc> {
+    let start = match loc.instr {
+        BBInstrIndex::Instr(i) => i,
+        BBInstrIndex::Terminator => loc.bb.instrs.len(),
+    };
+    loc.bb.instrs[start..]
+        .iter()
+        .find(|instr| matches!(instr, Instruction::Call(_)))
+        .and_then(|call| call.get_debug_loc().as_ref())
+        .or(loc.source_loc)
+        .map(SourceLoc::from)
+}
+
+/// Rebuild the call stack at the state's current location from haybale's path entries,
+/// outermost call first
+pub fn callstack(state: &State<DefaultBackend>) -> Vec<Frame> {
+    let demangling = Demangling::autodetect(state.proj);
+    let mut frames: Vec<(&Function, Option<SourceLoc>)> = Vec::new();
+    for entry in state.get_path() {
+        let loc = &entry.0;
+        match frames
+            .iter()
+            .position(|(func, _)| std::ptr::eq(*func, loc.func))
+        {
+            // Still in (or returned to) a function we already know about
+            Some(depth) => {
+                frames.truncate(depth + 1);
+                frames[depth].1 = entry_loc(loc);
+            }
+            None => frames.push((loc.func, entry_loc(loc))),
+        }
+    }
+    frames
+        .into_iter()
+        .map(|(func, loc)| Frame {
+            func: demangling.maybe_demangle(&func.name),
+            loc,
+        })
+        .collect()
+}
+
+/// One basic block a path went through
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    /// Demangled name of the function the block is in
+    pub func: String,
+    pub block: String,
+    /// First source location the path reached in the block
+    pub loc: Option<SourceLoc>,
+}
+
+impl fmt::Display for TraceStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.func, self.block)?;
+        if let Some(loc) = &self.loc {
+            write!(f, " ({})", loc)?;
+        }
+        Ok(())
+    }
+}
+
+/// The basic blocks the path to the state's current location went through, in order
+pub fn block_trace(state: &State<DefaultBackend>) -> Vec<TraceStep> {
+    let demangling = Demangling::autodetect(state.proj);
+    state
+        .get_path()
+        .iter()
+        .map(|entry| {
+            let loc = &entry.0;
+            let start = match loc.instr {
+                BBInstrIndex::Instr(i) => i,
+                BBInstrIndex::Terminator => loc.bb.instrs.len(),
+            };
+            TraceStep {
+                func: demangling.maybe_demangle(&loc.func.name),
+                block: loc.bb.name.to_string(),
+                loc: loc.bb.instrs[start..]
+                    .iter()
+                    .find_map(|instr| instr.get_debug_loc().as_ref())
+                    .or(loc.source_loc)
+                    .map(SourceLoc::from),
+            }
+        })
+        .collect()
+}
@@ -1,11 +1,25 @@
+use clap::Parser;
 use haybale::Project;
 use haybale::config::Demangling;
-mod checkers;
-mod exec;
-mod hooks;
-mod utils;
-use clap::Parser;
-use exec::symex_and_check;
+use llvm_ir::Function;
+use regex::Regex;
+use test_haybale::checkers::Violation;
+use test_haybale::checkers::double_fetch::DoubleFetchChecker;
+use test_haybale::checkers::oob::OobChecker;
+use test_haybale::exec::ExecOptions;
+use test_haybale::report::{FunctionReport, check_function, json, sarif};
+use test_haybale::sandbox::SandboxMemoryModel;
+
+/// How results are printed
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// Human-readable, one line per path plus details for each violation
+    Text,
+    /// A single JSON document, see `report::json`
+    Json,
+    /// SARIF 2.1.0, for code scanning tools
+    Sarif,
+}
 
 /// Command-line arguments
 #[derive(Parser, Debug)]
@@ -13,54 +27,243 @@ use exec::symex_and_check;
 struct Args {
     /// Path to the LLVM bitcode file (binary)
     binary: String,
-    /// Name of the function to symbolically execute
-    function: String,
+    /// Name of the function to symbolically execute, mangled or demangled
+    #[arg(required_unless_present = "scan")]
+    function: Option<String>,
+    /// Check every function defined in the binary instead of a single one
+    #[arg(long, conflicts_with = "function")]
+    scan: bool,
+    /// With --scan, only check functions whose demangled name matches this regex
+    #[arg(long, value_name = "REGEX", requires = "scan")]
+    filter: Option<Regex>,
+    /// Keep exploring after a violation and report every violation on each path
+    #[arg(long)]
+    exhaustive: bool,
+    /// Checker to turn off for this run (can be repeated)
+    #[arg(long = "disable", value_name = "CHECKER")]
+    disabled: Vec<String>,
+    /// Check every array index against its own dimension and struct field accesses against
+    /// the field, not just the whole object
+    #[arg(long)]
+    strict_bounds: bool,
+    /// Assume another guest thread can write sandbox memory at any time, so every read of it
+    /// through RLBox's accessors (UNSAFE_unverified, copy_and_verify and friends) may see a
+    /// different value. Raw loads through pointers the host got out of the sandbox still see
+    /// what was last stored
+    #[arg(long)]
+    volatile_sandbox_memory: bool,
+    /// Let a second accessor read of a sandbox location see a different value than the first,
+    /// and report violations that depend on that as double fetches
+    #[arg(long)]
+    double_fetch: bool,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
 }
 
 fn main() {
     env_logger::init();
     let args = Args::parse();
-    let loop_bound = 1000;
+    let mut options = ExecOptions {
+        exhaustive: args.exhaustive,
+        sandbox_memory: if args.volatile_sandbox_memory {
+            SandboxMemoryModel::Volatile
+        } else {
+            SandboxMemoryModel::SingleThreaded
+        },
+        ..ExecOptions::default()
+    };
+    if args.strict_bounds {
+        options.checkers.register(OobChecker::strict());
+    }
+    if args.double_fetch {
+        options.checkers.register(DoubleFetchChecker);
+    }
+    for name in &args.disabled {
+        if !options.checkers.set_enabled(name, false) {
+            eprintln!("Error: no checker named '{}'", name);
+            std::process::exit(1);
+        }
+    }
 
     let project = Project::from_bc_path(&args.binary).unwrap();
-    let (func, module) = project.get_func_by_name(&args.function).unwrap_or_else(|| {
-        eprintln!(
-            "Error: Function '{}' not found in the binary '{}'",
-            args.function, args.binary
+    let demangling = Demangling::autodetect(&project);
+    let text = args.format == Format::Text;
+
+    let targets = if args.scan {
+        scan_targets(&project, &demangling, args.filter.as_ref())
+    } else {
+        let function = args.function.as_deref().unwrap();
+        let Some(func) = find_function(&project, &demangling, function) else {
+            eprintln!(
+                "Error: Function '{}' not found in the binary '{}'",
+                function, args.binary
+            );
+            std::process::exit(1);
+        };
+        let (_, module) = project.get_func_by_name(&func.name).unwrap();
+
+        // Detect demangling strategy and demangle the function name
+        let demangled_func_name = demangling.maybe_demangle(&func.name);
+
+        if text {
+            println!(
+                "Analyzing function {:?} from module {:?}",
+                demangled_func_name, module.name
+            );
+        }
+        log::info!(
+            "Function signature: {:?} -> {:?}",
+            func.parameters,
+            func.return_type
         );
+        for block in &func.basic_blocks {
+            log::info!("Basic block {}", block.name);
+            for instr in &block.instrs {
+                log::info!("--- Instruction: {}", instr);
+            }
+            log::info!("\n");
+        }
+        vec![func.name.clone()]
+    };
+
+    let mut reports = Vec::new();
+    for name in &targets {
+        let report = check_function(name, &project, &options);
+        if text {
+            if args.scan {
+                println!("== {}", report.demangled);
+            }
+            print_report(&report);
+        }
+        reports.push(report);
+    }
+
+    match args.format {
+        Format::Text if args.scan => print_summary(&reports),
+        Format::Text => {}
+        Format::Json => println!("{}", json::to_json(&args.binary, &reports)),
+        Format::Sarif => println!("{}", sarif::to_sarif(&reports, &options.checkers)),
+    }
+    // Let the nightly job fail on findings
+    let clean = reports
+        .iter()
+        .all(|report| report.outcome.is_ok() && report.violations().next().is_none());
+    if args.scan && !clean {
         std::process::exit(1);
-    });
+    }
+}
 
-    // Detect demangling strategy and demangle the function name
-    let demangling = Demangling::autodetect(&project);
-    let demangled_func_name = demangling.maybe_demangle(&func.name);
+// Accept either the mangled name or the demangled one, with or without its parameter list
+fn find_function<'p>(
+    project: &'p Project,
+    demangling: &Demangling,
+    name: &str,
+) -> Option<&'p Function> {
+    if let Some((func, _)) = project.get_func_by_name(name) {
+        return Some(func);
+    }
+    project.all_functions().map(|(func, _)| func).find(|func| {
+        let demangled = demangling.maybe_demangle(&func.name);
+        demangled == name
+            || demangled
+                .strip_prefix(name)
+                .is_some_and(|rest| rest.starts_with('('))
+    })
+}
 
-    println!(
-        "Analyzing function {:?} from module {:?}",
-        demangled_func_name, module.name
-    );
-    log::info!(
-        "Function signature: {:?} -> {:?}",
-        func.parameters,
-        func.return_type
-    );
-    for block in &func.basic_blocks {
-        log::info!("Basic block {}", block.name);
-        for instr in &block.instrs {
-            log::info!("--- Instruction: {}", instr);
+/// Every defined function whose demangled name matches `filter`, ordered by demangled name
+fn scan_targets(project: &Project, demangling: &Demangling, filter: Option<&Regex>) -> Vec<String> {
+    let mut funcs: Vec<(String, String)> = project
+        .all_functions()
+        .map(|(func, _)| (func.name.clone(), demangling.maybe_demangle(&func.name)))
+        .filter(|(_, demangled)| filter.is_none_or(|filter| filter.is_match(demangled)))
+        .collect();
+    funcs.sort_by(|a, b| a.1.cmp(&b.1));
+    funcs.into_iter().map(|(name, _)| name).collect()
+}
+
+fn print_report(report: &FunctionReport) {
+    let paths = match &report.outcome {
+        Ok(paths) => paths,
+        Err(failure) => {
+            println!("analysis failed: {}", failure);
+            return;
+        }
+    };
+    for (i, path) in paths.iter().enumerate() {
+        match &path.result {
+            Ok(()) => println!("Path {}: ok", i + 1),
+            Err(violations) => {
+                for violation in violations {
+                    print_violation(i, violation);
+                }
+            }
+        }
+        for warning in &path.warnings {
+            print_violation(i, warning);
         }
-        log::info!("\n");
     }
+}
 
-    let trace = symex_and_check(&args.function, &project, loop_bound);
-    println!("trace: {:?}", trace);
-    // if trace.len() == 1 {
-    //     log::info!("We found 1 possible path");
-    //     log::info!("Path: {:?}", trace[0].0);
-    // } else {
-    //     log::info!("We found {} possible paths", trace.len());
-    //     for (i, path) in trace.iter().enumerate() {
-    //         log::info!("Path {}: {:?}", i + 1, path.0);
-    //     }
-    // }
+fn print_violation(path_index: usize, violation: &Violation) {
+    println!("Path {}: {}", path_index + 1, violation);
+    for frame in violation.callstack.iter().rev() {
+        println!("    at {}", frame);
+    }
+    for (label, site) in &violation.related {
+        println!("    {}: {}", label, site);
+    }
+    if let Some(counterexample) = &violation.counterexample {
+        for line in counterexample.to_string().lines() {
+            println!("    {}", line);
+        }
+    }
+}
+
+fn summary_status(report: &FunctionReport) -> String {
+    match &report.outcome {
+        Err(failure) => format!("failed: {}", failure.lines().next().unwrap_or_default()),
+        Ok(_) if report.violations().next().is_some() => "violations".to_string(),
+        Ok(_) => "ok".to_string(),
+    }
+}
+
+fn print_summary(reports: &[FunctionReport]) {
+    let width = reports
+        .iter()
+        .map(|report| report.demangled.len())
+        .chain(std::iter::once("function".len()))
+        .max()
+        .unwrap();
+    println!();
+    println!(
+        "{:<width$}  {:>5}  {:>9}  {:>10}  {:>8}  status",
+        "function", "paths", "violating", "violations", "warnings"
+    );
+    for report in reports {
+        println!(
+            "{:<width$}  {:>5}  {:>9}  {:>10}  {:>8}  {}",
+            report.demangled,
+            report.paths().len(),
+            report.violating_paths(),
+            report.violations().count(),
+            report.warnings().count(),
+            summary_status(report)
+        );
+    }
+    let failed = reports
+        .iter()
+        .filter(|report| report.outcome.is_err())
+        .count();
+    let flagged = reports
+        .iter()
+        .filter(|report| report.violations().next().is_some())
+        .count();
+    println!(
+        "\n{} functions checked, {} with violations, {} failed",
+        reports.len(),
+        flagged,
+        failed
+    );
 }
@@ -0,0 +1,208 @@
+use super::FunctionReport;
+use crate::checkers::Violation;
+use crate::counterexample::Counterexample;
+use crate::exec::{CheckedPath, PathStatus};
+use crate::location::{Frame, Site, SourceLoc};
+use serde::Serialize;
+
+/// Bumped whenever a field changes meaning or goes away; new fields may appear without a bump
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Report<'r> {
+    schema_version: u32,
+    binary: &'r str,
+    functions: Vec<Function<'r>>,
+}
+
+#[derive(Serialize)]
+struct Function<'r> {
+    name: &'r str,
+    demangled: &'r str,
+    /// "analyzed" or "failed"
+    status: &'static str,
+    /// Why the analysis failed, if it did
+    failure: Option<&'r str>,
+    paths: Vec<Path<'r>>,
+}
+
+#[derive(Serialize)]
+struct Path<'r> {
+    /// 1-based, in exploration order
+    index: usize,
+    /// "completed", "violation", "loop_bound" or "error"
+    status: &'static str,
+    /// Haybale's error, for paths with status "error"
+    error: Option<&'r str>,
+    /// Errors first, then warnings
+    findings: Vec<Finding<'r>>,
+}
+
+#[derive(Serialize)]
+struct Finding<'r> {
+    checker: &'r str,
+    kind: &'r str,
+    /// "error" or "warning"
+    severity: &'static str,
+    /// Whether the offending instruction consumes a value the sandbox can influence
+    sandbox_controlled: bool,
+    function: &'r str,
+    demangled_function: &'r str,
+    instruction: &'r str,
+    location: Option<Location<'r>>,
+    /// Outermost call first
+    callstack: Vec<StackFrame<'r>>,
+    /// Other program points involved, e.g. both reads of a double fetch
+    related: Vec<Related<'r>>,
+    counterexample: Option<CounterexampleJson<'r>>,
+}
+
+#[derive(Serialize)]
+struct Location<'r> {
+    file: &'r str,
+    directory: Option<&'r str>,
+    line: u32,
+    column: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct StackFrame<'r> {
+    function: &'r str,
+    location: Option<Location<'r>>,
+}
+
+#[derive(Serialize)]
+struct Related<'r> {
+    label: &'r str,
+    function: &'r str,
+    instruction: &'r str,
+    location: Option<Location<'r>>,
+}
+
+#[derive(Serialize)]
+struct CounterexampleJson<'r> {
+    params: Vec<NamedValue<'r>>,
+    sandbox_reads: Vec<NamedValue<'r>>,
+    values: Vec<NamedValue<'r>>,
+}
+
+#[derive(Serialize)]
+struct NamedValue<'r> {
+    name: &'r str,
+    value: u64,
+}
+
+fn location(loc: &SourceLoc) -> Location<'_> {
+    Location {
+        file: &loc.file,
+        directory: loc.directory.as_deref(),
+        line: loc.line,
+        column: loc.col,
+    }
+}
+
+fn named_values(values: &[(String, u64)]) -> Vec<NamedValue<'_>> {
+    values
+        .iter()
+        .map(|(name, value)| NamedValue {
+            name,
+            value: *value,
+        })
+        .collect()
+}
+
+fn counterexample(counterexample: &Counterexample) -> CounterexampleJson<'_> {
+    CounterexampleJson {
+        params: named_values(&counterexample.params),
+        sandbox_reads: named_values(&counterexample.sandbox_reads),
+        values: named_values(&counterexample.values),
+    }
+}
+
+fn stack_frame(frame: &Frame) -> StackFrame<'_> {
+    StackFrame {
+        function: &frame.func,
+        location: frame.loc.as_ref().map(location),
+    }
+}
+
+fn related<'r>(label: &'r str, site: &'r Site) -> Related<'r> {
+    Related {
+        label,
+        function: &site.func,
+        instruction: &site.instr,
+        location: site.loc.as_ref().map(location),
+    }
+}
+
+fn finding(violation: &Violation) -> Finding<'_> {
+    Finding {
+        checker: violation.checker,
+        kind: violation.kind.name(),
+        severity: violation.severity.name(),
+        sandbox_controlled: violation.sandbox_controlled,
+        function: &violation.func,
+        demangled_function: &violation.demangled_func,
+        instruction: &violation.instr,
+        location: violation.loc.as_ref().map(location),
+        callstack: violation.callstack.iter().map(stack_frame).collect(),
+        related: violation
+            .related
+            .iter()
+            .map(|(label, site)| related(label, site))
+            .collect(),
+        counterexample: violation.counterexample.as_ref().map(counterexample),
+    }
+}
+
+fn path(index: usize, path: &CheckedPath) -> Path<'_> {
+    let (status, error) = match &path.status {
+        PathStatus::Completed => ("completed", None),
+        PathStatus::Violation => ("violation", None),
+        PathStatus::LoopBound => ("loop_bound", None),
+        PathStatus::Error(err) => ("error", Some(err.as_str())),
+    };
+    Path {
+        index: index + 1,
+        status,
+        error,
+        findings: path
+            .result
+            .as_ref()
+            .err()
+            .into_iter()
+            .flatten()
+            .chain(&path.warnings)
+            .map(finding)
+            .collect(),
+    }
+}
+
+fn function(report: &FunctionReport) -> Function<'_> {
+    Function {
+        name: &report.name,
+        demangled: &report.demangled,
+        status: if report.outcome.is_ok() {
+            "analyzed"
+        } else {
+            "failed"
+        },
+        failure: report.outcome.as_ref().err().map(String::as_str),
+        paths: report
+            .paths()
+            .iter()
+            .enumerate()
+            .map(|(i, checked)| path(i, checked))
+            .collect(),
+    }
+}
+
+/// Render the reports for `binary` as a pretty-printed JSON document
+pub fn to_json(binary: &str, reports: &[FunctionReport]) -> String {
+    let report = Report {
+        schema_version: SCHEMA_VERSION,
+        binary,
+        functions: reports.iter().map(function).collect(),
+    };
+    serde_json::to_string_pretty(&report).unwrap()
+}
@@ -0,0 +1,61 @@
+pub mod json;
+pub mod sarif;
+
+use crate::checkers::Violation;
+use crate::exec::{CheckedPath, ExecOptions, symex_and_check_paths};
+use haybale::Project;
+use haybale::config::Demangling;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Everything found while checking one function
+#[derive(Debug)]
+pub struct FunctionReport {
+    pub name: String,
+    pub demangled: String,
+    /// The checked paths, or why the function could not be analyzed
+    pub outcome: Result<Vec<CheckedPath>, String>,
+}
+
+impl FunctionReport {
+    pub fn paths(&self) -> &[CheckedPath] {
+        self.outcome.as_deref().unwrap_or_default()
+    }
+
+    pub fn violations(&self) -> impl Iterator<Item = &Violation> {
+        self.paths()
+            .iter()
+            .filter_map(|path| path.result.as_ref().err())
+            .flatten()
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Violation> {
+        self.paths().iter().flat_map(|path| &path.warnings)
+    }
+
+    pub fn violating_paths(&self) -> usize {
+        self.paths()
+            .iter()
+            .filter(|path| path.result.is_err())
+            .count()
+    }
+}
+
+/// Check a single function. Haybale panics on some constructs it doesn't support; that is
+/// reported as the function failing rather than taking the caller down with it.
+pub fn check_function(name: &str, project: &Project, options: &ExecOptions) -> FunctionReport {
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        symex_and_check_paths(name, project, options)
+    }))
+    .map_err(|payload| {
+        payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "panicked".to_string())
+    });
+    FunctionReport {
+        name: name.to_string(),
+        demangled: Demangling::autodetect(project).maybe_demangle(name),
+        outcome,
+    }
+}
@@ -0,0 +1,150 @@
+use super::FunctionReport;
+use crate::checkers::{Registry, Violation};
+use crate::location::SourceLoc;
+use serde_json::{Value, json};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+fn physical_location(loc: &SourceLoc) -> Value {
+    let mut region = json!({ "startLine": loc.line });
+    if let Some(col) = loc.col {
+        region["startColumn"] = json!(col);
+    }
+    json!({
+        "artifactLocation": { "uri": loc.file },
+        "region": region,
+    })
+}
+
+fn location(loc: Option<&SourceLoc>, message: String) -> Value {
+    let mut location = json!({ "message": { "text": message } });
+    if let Some(loc) = loc {
+        location["physicalLocation"] = physical_location(loc);
+    }
+    location
+}
+
+// One thread flow through the basic blocks the path took, ending at the violation
+fn code_flow(violation: &Violation) -> Value {
+    let mut steps: Vec<Value> = violation
+        .trace
+        .iter()
+        .map(|step| {
+            json!({
+                "location": location(
+                    step.loc.as_ref(),
+                    format!("{}: {}", step.func, step.block),
+                ),
+            })
+        })
+        .collect();
+    steps.push(json!({
+        "location": location(violation.loc.as_ref(), violation.to_string()),
+        "importance": "essential",
+    }));
+    json!({ "threadFlows": [{ "locations": steps }] })
+}
+
+fn related_locations(violation: &Violation) -> Vec<Value> {
+    violation
+        .related
+        .iter()
+        .enumerate()
+        .map(|(id, (label, site))| {
+            let mut related = location(site.loc.as_ref(), format!("{}: {}", label, site));
+            related["id"] = json!(id);
+            related
+        })
+        .collect()
+}
+
+fn result(violation: &Violation, rule_index: Option<usize>) -> Value {
+    let mut message = violation.to_string();
+    if let Some(counterexample) = &violation.counterexample {
+        for line in counterexample.to_string().lines() {
+            message.push_str("\n    ");
+            message.push_str(line);
+        }
+    }
+    let mut location = json!({
+        "logicalLocations": [{
+            "name": violation.demangled_func,
+            "decoratedName": violation.func,
+            "kind": "function",
+        }],
+    });
+    if let Some(loc) = &violation.loc {
+        location["physicalLocation"] = physical_location(loc);
+    }
+    let mut result = json!({
+        "ruleId": violation.checker,
+        "level": violation.severity.name(),
+        "message": { "text": message },
+        "locations": [location],
+        "codeFlows": [code_flow(violation)],
+        "relatedLocations": related_locations(violation),
+        "properties": {
+            "kind": violation.kind.name(),
+            "sandboxControlled": violation.sandbox_controlled,
+        },
+    });
+    if let Some(index) = rule_index {
+        result["ruleIndex"] = json!(index);
+    }
+    result
+}
+
+/// Render the reports as a SARIF 2.1.0 log with one rule per registered checker
+pub fn to_sarif(reports: &[FunctionReport], checkers: &Registry) -> String {
+    let rule_ids: Vec<&str> = checkers.all().map(|checker| checker.name()).collect();
+    let rules: Vec<Value> = checkers
+        .all()
+        .map(|checker| {
+            json!({
+                "id": checker.name(),
+                "shortDescription": { "text": checker.description() },
+            })
+        })
+        .collect();
+
+    let results: Vec<Value> = reports
+        .iter()
+        .flat_map(|report| report.violations().chain(report.warnings()))
+        .map(|violation| {
+            let rule_index = rule_ids.iter().position(|id| *id == violation.checker);
+            result(violation, rule_index)
+        })
+        .collect();
+
+    // Functions we could not analyze at all are reported as tool notifications
+    let notifications: Vec<Value> = reports
+        .iter()
+        .filter_map(|report| {
+            let failure = report.outcome.as_ref().err()?;
+            Some(json!({
+                "level": "error",
+                "message": { "text": format!("analysis of {} failed: {}", report.demangled, failure) },
+            }))
+        })
+        .collect();
+
+    let log = json!({
+        "$schema": SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "invocations": [{
+                "executionSuccessful": notifications.is_empty(),
+                "toolExecutionNotifications": notifications,
+            }],
+            "results": results,
+        }],
+    });
+    serde_json::to_string_pretty(&log).unwrap()
+}
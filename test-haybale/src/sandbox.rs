@@ -0,0 +1,519 @@
+use crate::allocations::{
+    AllocKind, Allocation, POINTER_BITS, allocation_containing, record_allocation, to_pointer_width,
+};
+use crate::checkers::double_fetch::{DoubleFetch, record_double_fetch};
+use crate::context::with_context;
+use crate::location::Site;
+use haybale::backend::Backend;
+use haybale::backend::DefaultBackend;
+use haybale::{Error, State};
+use llvm_ir::{Name, Operand, Type};
+
+type BV = <DefaultBackend as Backend>::BV;
+
+/// Address space reserved for each sandbox's memory
+pub const REGION_BYTES: u64 = 1 << 24;
+
+/// The longest string the host takes out of the sandbox. A verifier passed to
+/// `copy_and_verify_string` has to keep copies shorter than this.
+pub const HOST_STRING_LIMIT: u64 = 1024;
+
+// malloc_in_sandbox hands out blocks at this alignment
+const ALIGNMENT: u64 = 16;
+
+// How much of each block gets fresh contents at a transition. Whatever lies past this, in
+// blocks that large or of unknown size, is freshened when the host reads it instead.
+const HAVOC_BYTES: u64 = 1 << 16;
+
+// Fresh contents are written this many bytes at a time
+const HAVOC_CHUNK_BYTES: u64 = 512;
+
+/// When the contents of sandbox memory can change under the host
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SandboxMemoryModel {
+    /// Only while the guest runs, i.e. across sandbox transitions. Matches
+    /// `RLBOX_SINGLE_THREADED_INVOCATIONS` with no other thread in the sandbox.
+    #[default]
+    SingleThreaded,
+    /// At any time, as another guest thread may be writing to it: every read of sandbox memory
+    /// through the accessors we hook (`UNSAFE_unverified`, `get_raw_value` under the
+    /// `copy_and_verify*` validators, `unverified_safe_pointer_because`) sees fresh contents.
+    ///
+    /// Raw loads, e.g. through a pointer `unverified_safe_pointer_because` handed out, are not
+    /// covered: haybale only lets instruction callbacks look at the state, not change what a
+    /// load returns or write memory ahead of it. Two raw loads of the same location with no
+    /// sandbox transition in between always agree.
+    Volatile,
+}
+
+/// The memory a sandbox owns, kept apart from host memory.
+///
+/// Everything in it is under the guest's control: `malloc_in_sandbox` carves blocks out of it,
+/// and whenever the guest gets to run, those blocks get fresh contents.
+#[derive(Debug, Clone)]
+pub struct SandboxRegion {
+    /// Address of the `rlbox_sandbox` object the region belongs to
+    pub sandbox: BV,
+    pub base: BV,
+    pub end: BV,
+    /// Bytes handed out so far
+    pub used: u64,
+}
+
+impl SandboxRegion {
+    /// The condition under which `addr` points into the region
+    pub fn contains(&self, addr: &BV) -> BV {
+        addr.ugte(&self.base).and(&addr.ult(&self.end))
+    }
+}
+
+/// Where a sandbox object is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lifecycle {
+    /// The `rlbox_sandbox` object exists, but `create_sandbox` hasn't been called on it
+    Constructed,
+    Created,
+    Destroyed,
+}
+
+/// A sandbox object moving to a new stage of its life
+#[derive(Debug, Clone)]
+pub struct SandboxLifecycle {
+    /// Address of the `rlbox_sandbox` object
+    pub sandbox: BV,
+    pub stage: Lifecycle,
+    /// The call that moved it there
+    pub site: Site,
+}
+
+/// Note that the sandbox object at `sandbox` moved to `stage` at the current instruction
+pub fn record_lifecycle(state: &State<DefaultBackend>, sandbox: BV, stage: Lifecycle) {
+    let lifecycle = SandboxLifecycle {
+        sandbox,
+        stage,
+        site: Site::current(state),
+    };
+    log::debug!("sandbox lifecycle: {:?}", lifecycle);
+    with_context(|ctx| ctx.sandbox_lifecycles.record(state, lifecycle));
+}
+
+/// Every stage the sandbox object at `sandbox` went through on this path, oldest first.
+/// Empty if we never saw it being constructed or created.
+pub fn lifecycle_of(state: &State<DefaultBackend>, sandbox: &BV) -> Vec<SandboxLifecycle> {
+    with_context(|ctx| {
+        ctx.sandbox_lifecycles
+            .visible(state)
+            .filter(|lifecycle| {
+                state
+                    .bvs_must_be_equal(&lifecycle.sandbox, sandbox)
+                    .unwrap()
+            })
+            .cloned()
+            .collect()
+    })
+}
+
+/// The sandbox objects seen on this path, each with the stage it is at now
+pub fn current_lifecycles(state: &State<DefaultBackend>) -> Vec<SandboxLifecycle> {
+    let mut current: Vec<SandboxLifecycle> = Vec::new();
+    let all: Vec<SandboxLifecycle> =
+        with_context(|ctx| ctx.sandbox_lifecycles.visible(state).cloned().collect());
+    for lifecycle in all {
+        match current.iter_mut().find(|seen| {
+            state
+                .bvs_must_be_equal(&seen.sandbox, &lifecycle.sandbox)
+                .unwrap()
+        }) {
+            Some(seen) => *seen = lifecycle,
+            None => current.push(lifecycle),
+        }
+    }
+    current
+}
+
+/// The region of the sandbox object at `sandbox`, if it was created on this path
+pub fn region_of(state: &State<DefaultBackend>, sandbox: &BV) -> Option<SandboxRegion> {
+    with_context(|ctx| {
+        ctx.sandbox_regions
+            .visible(state)
+            .filter(|region| state.bvs_must_be_equal(&region.sandbox, sandbox).unwrap())
+            .last()
+            .cloned()
+    })
+}
+
+/// Reserve a fresh region for the sandbox object at `sandbox`
+pub fn create_region(state: &mut State<DefaultBackend>, sandbox: BV) -> SandboxRegion {
+    let base = state.allocate(REGION_BYTES * 8);
+    let end = base.add(&state.bv_from_u64(REGION_BYTES, POINTER_BITS));
+    let region = SandboxRegion {
+        sandbox,
+        base,
+        end,
+        used: 0,
+    };
+    log::debug!("sandbox region: {:?}", region);
+    with_context(|ctx| ctx.sandbox_regions.record(state, region.clone()));
+    region
+}
+
+/// Carve a block of `size` bytes out of the region of the sandbox at `sandbox` and return its
+/// address, or null if the request can't fit
+pub fn malloc_in_sandbox(
+    state: &mut State<DefaultBackend>,
+    sandbox: BV,
+    size: &BV,
+) -> Result<BV, Error> {
+    let mut region = match region_of(state, &sandbox) {
+        Some(region) => region,
+        // The sandbox was created somewhere we didn't see, e.g. before the entry function
+        None => create_region(state, sandbox),
+    };
+    let size = to_pointer_width(size);
+    let remaining = state.bv_from_u64(REGION_BYTES - region.used, POINTER_BITS);
+    let fits = size.ulte(&remaining);
+    if !state.sat_with_extra_constraints(std::iter::once(&fits))? {
+        return Ok(state.zero(POINTER_BITS));
+    }
+    // Requests too large for the region would come back null; we only follow the ones that fit
+    fits.assert();
+    let reserved = state
+        .max_possible_solution_for_bv_as_u64(&size)?
+        .unwrap_or(0);
+    let address = region
+        .base
+        .add(&state.bv_from_u64(region.used, POINTER_BITS));
+    region.used = (region.used + reserved)
+        .next_multiple_of(ALIGNMENT)
+        .min(REGION_BYTES);
+    with_context(|ctx| ctx.sandbox_regions.record(state, region));
+    record_allocation(state, address.clone(), size, AllocKind::Sandbox);
+    Ok(address)
+}
+
+/// A block handed out by `malloc_in_sandbox` being given back with `free_in_sandbox`
+#[derive(Debug, Clone)]
+pub struct SandboxFree {
+    pub block: Allocation,
+    pub site: Site,
+}
+
+/// The block `malloc_in_sandbox` handed out at `base` on this path, freed or not
+pub fn sandbox_block_at(state: &State<DefaultBackend>, base: &BV) -> Option<Allocation> {
+    with_context(|ctx| {
+        ctx.allocations
+            .visible(state)
+            .filter(|allocation| allocation.kind == AllocKind::Sandbox)
+            .filter(|allocation| state.bvs_must_be_equal(&allocation.base, base).unwrap())
+            .last()
+            .cloned()
+    })
+}
+
+/// Every sandbox block freed on this path, oldest first
+pub fn freed_blocks(state: &State<DefaultBackend>) -> Vec<SandboxFree> {
+    with_context(|ctx| ctx.sandbox_frees.visible(state).cloned().collect())
+}
+
+/// Give back the sandbox block at `pointer`. Its memory is never handed out again, so that
+/// host accesses to it after this can be caught. Pointers that aren't the start of a block are
+/// left alone.
+pub fn free_in_sandbox(state: &State<DefaultBackend>, pointer: &BV) {
+    let Some(block) = sandbox_block_at(state, &to_pointer_width(pointer)) else {
+        return;
+    };
+    let free = SandboxFree {
+        block,
+        site: Site::current(state),
+    };
+    log::debug!("sandbox free: {:?}", free);
+    with_context(|ctx| ctx.sandbox_frees.record(state, free));
+}
+
+/// The pointer a `tainted<T*>` argument holds. Depending on how the call was lowered, the
+/// argument is the pointer itself or the address of the tainted object wrapping it.
+pub fn tainted_pointer_arg(state: &State<DefaultBackend>, arg: &Operand) -> Result<BV, Error> {
+    let arg_bv = state.operand_to_bv(arg)?;
+    match state.type_of(arg).as_ref() {
+        Type::PointerType { pointee_type, .. }
+            if matches!(pointee_type.as_ref(), Type::NamedStructType { .. }) =>
+        {
+            state.read(&arg_bv, POINTER_BITS)
+        }
+        _ => Ok(to_pointer_width(&arg_bv)),
+    }
+}
+
+/// The condition under which `addr` points into the memory of any sandbox created on this path
+pub fn in_sandbox_memory(state: &State<DefaultBackend>, addr: &BV) -> Option<BV> {
+    with_context(|ctx| {
+        ctx.sandbox_regions
+            .visible(state)
+            .map(|region| region.contains(addr))
+            .reduce(|any, inside| any.or(&inside))
+    })
+}
+
+/// The part of a sandbox block too far in to havoc, where every host read through an accessor
+/// sees fresh contents
+#[derive(Debug, Clone)]
+pub struct StaleSandboxMemory {
+    pub start: BV,
+    pub end: BV,
+}
+
+/// Give every block handed out of sandbox memory fresh contents. Called at every transition
+/// into the sandbox: a compromised guest may have rewritten any of its own memory by the time
+/// control comes back, so nothing the host wrote there earlier can be relied on.
+///
+/// Only the first `HAVOC_BYTES` of a block are rewritten; the rest is marked stale, for
+/// `read_sandbox_memory` to freshen. Raw loads from a stale part still see what was there.
+pub fn havoc_sandbox_memory(state: &mut State<DefaultBackend>) -> Result<(), Error> {
+    let blocks: Vec<Allocation> = with_context(|ctx| {
+        ctx.allocations
+            .visible(state)
+            .filter(|allocation| allocation.kind == AllocKind::Sandbox)
+            .cloned()
+            .collect()
+    });
+    for block in blocks {
+        // A symbolic size can be as large as the region, so this is only an upper bound
+        let size = state
+            .max_possible_solution_for_bv_as_u64(&block.size)?
+            .unwrap_or(0);
+        let havocked = size.min(HAVOC_BYTES);
+        let mut offset = 0;
+        while offset < havocked {
+            let chunk = (havocked - offset).min(HAVOC_CHUNK_BYTES);
+            let fresh = state.new_bv_with_name(Name::from("sandbox_memory"), (chunk * 8) as u32)?;
+            let addr = block.base.add(&state.bv_from_u64(offset, POINTER_BITS));
+            state.write(&addr, fresh)?;
+            offset += chunk;
+        }
+        if size > HAVOC_BYTES {
+            mark_stale(state, &block);
+        }
+    }
+    Ok(())
+}
+
+fn mark_stale(state: &State<DefaultBackend>, block: &Allocation) {
+    let start = block
+        .base
+        .add(&state.bv_from_u64(HAVOC_BYTES, POINTER_BITS));
+    let marked = with_context(|ctx| {
+        ctx.stale_sandbox_memory
+            .visible(state)
+            .any(|stale| state.bvs_must_be_equal(&stale.start, &start).unwrap())
+    });
+    if !marked {
+        let stale = StaleSandboxMemory {
+            start,
+            end: block.base.add(&block.size),
+        };
+        with_context(|ctx| ctx.stale_sandbox_memory.record(state, stale));
+    }
+}
+
+// Whether `[addr, addr + bits)` must lie in a stale part of a block
+fn is_stale(state: &State<DefaultBackend>, addr: &BV, bits: u32) -> Result<bool, Error> {
+    let stale: Vec<StaleSandboxMemory> =
+        with_context(|ctx| ctx.stale_sandbox_memory.visible(state).cloned().collect());
+    let end = addr.add(&state.bv_from_u32(bits.div_ceil(8), POINTER_BITS));
+    for part in stale {
+        let inside = addr.ugte(&part.start).and(&end.ulte(&part.end));
+        if !state.sat_with_extra_constraints(std::iter::once(&inside.not()))? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// The condition under which `[start, start + size)` lies inside the memory of a single
+/// sandbox, if any sandbox was created on this path
+pub fn range_in_sandbox_memory(state: &State<DefaultBackend>, start: &BV, size: &BV) -> Option<BV> {
+    let start = to_pointer_width(start);
+    let end = start.add(&to_pointer_width(size));
+    with_context(|ctx| {
+        ctx.sandbox_regions
+            .visible(state)
+            .map(|region| {
+                start
+                    .ugte(&region.base)
+                    .and(&end.ulte(&region.end))
+                    .and(&end.ugte(&start))
+            })
+            .reduce(|any, inside| any.or(&inside))
+    })
+}
+
+/// Remember `[start, start + size)` as all the host may access through the guest pointer
+/// `start`
+pub fn record_granted_range(state: &State<DefaultBackend>, start: &BV, size: &BV) {
+    record_allocation(
+        state,
+        to_pointer_width(start),
+        to_pointer_width(size),
+        AllocKind::Granted,
+    );
+}
+
+/// Assume `[start, start + size)` lies inside the memory of a single sandbox, as RLBox checks
+/// before it lets the host use a guest pointer for that many bytes, and record the range as
+/// granted.
+///
+/// Fails with `Error::Unsat` if the range can't be in sandbox memory, where RLBox would abort.
+pub fn grant_range(state: &mut State<DefaultBackend>, start: &BV, size: &BV) -> Result<(), Error> {
+    // With no sandbox created on this path, we don't know where its memory is
+    if let Some(inside) = range_in_sandbox_memory(state, start, size) {
+        if !state.sat_with_extra_constraints(std::iter::once(&inside))? {
+            return Err(Error::Unsat);
+        }
+        inside.assert();
+    }
+    record_granted_range(state, start, size);
+    Ok(())
+}
+
+/// A string in sandbox memory, as `strlen` measured it
+#[derive(Debug, Clone)]
+pub struct SandboxString {
+    pub addr: BV,
+    pub len: BV,
+    /// Whether the terminator lies inside the block the string starts in, if we know that block
+    pub terminated: Option<BV>,
+    pub site: Site,
+}
+
+impl SandboxString {
+    /// The condition under which the string reaches `HOST_STRING_LIMIT`, or runs past the end
+    /// of its block
+    pub fn unchecked(&self, state: &State<DefaultBackend>) -> BV {
+        let unbounded = self
+            .len
+            .ugte(&state.bv_from_u64(HOST_STRING_LIMIT, POINTER_BITS));
+        match &self.terminated {
+            Some(terminated) => unbounded.or(&terminated.not()),
+            None => unbounded,
+        }
+    }
+}
+
+/// Measure the string at `addr` in sandbox memory, as `strlen` would.
+///
+/// Where the terminator is, if anywhere, is up to the guest, so the length is fresh, and the
+/// string may well run past the end of its block. We only follow the cases where it ends
+/// inside the region: RLBox refuses ranges that leave it altogether. The first `scan_bytes`
+/// bytes of the string are nonzero up to the terminator, so that a later scan of a copy finds
+/// the same length.
+pub fn sandbox_strlen(
+    state: &mut State<DefaultBackend>,
+    addr: &BV,
+    scan_bytes: u64,
+) -> Result<SandboxString, Error> {
+    let addr = to_pointer_width(addr);
+    let len = state.new_bv_with_name(Name::from("sandbox_strlen"), POINTER_BITS)?;
+    let region_end = with_context(|ctx| {
+        ctx.sandbox_regions
+            .visible(state)
+            .filter(|region| {
+                state
+                    .sat_with_extra_constraints(std::iter::once(&region.contains(&addr)))
+                    .unwrap()
+            })
+            .last()
+            .map(|region| region.end.clone())
+    });
+    if let Some(end) = region_end {
+        len.ult(&end.sub(&addr)).assert();
+    }
+    let zero = state.zero(8);
+    for i in 0..scan_bytes {
+        let offset = state.bv_from_u64(i, POINTER_BITS);
+        let byte = state.read(&addr.add(&offset), 8)?;
+        len.ulte(&offset).or(&byte._ne(&zero)).assert();
+    }
+    let terminator = addr.add(&len);
+    state.write(&terminator, zero)?;
+    let terminated = allocation_containing(state, &addr)
+        .map(|block| len.ult(&block.base.add(&block.size).sub(&addr)));
+    Ok(SandboxString {
+        addr,
+        len,
+        terminated,
+        site: Site::current(state),
+    })
+}
+
+/// A read of sandbox memory through one of the accessors
+#[derive(Debug, Clone)]
+pub struct SandboxFetch {
+    pub addr: BV,
+    /// What the location held at the time
+    pub stored: BV,
+    pub site: Site,
+}
+
+/// Read `bits` bits at `addr` on behalf of the host, through one of the sandbox accessors.
+///
+/// Under `SandboxMemoryModel::Volatile`, a read that must land in sandbox memory returns a
+/// fresh value instead of what is stored there. When double fetches are tracked, so does
+/// reading a location again that nothing wrote to since; the double fetch is remembered so
+/// checkers can tell when a violation depends on the two reads differing.
+pub fn read_sandbox_memory(
+    state: &mut State<DefaultBackend>,
+    addr: &BV,
+    bits: u32,
+) -> Result<BV, Error> {
+    let (model, track_double_fetches) =
+        with_context(|ctx| (ctx.sandbox_memory, ctx.track_double_fetches));
+    let in_sandbox = match in_sandbox_memory(state, addr) {
+        Some(inside) => !state.sat_with_extra_constraints(std::iter::once(&inside.not()))?,
+        None => false,
+    };
+    if !in_sandbox {
+        return state.read(addr, bits);
+    }
+    if is_stale(state, addr, bits)? {
+        return state.new_bv_with_name(Name::from("sandbox_memory"), bits);
+    }
+    if model == SandboxMemoryModel::Volatile {
+        return state.new_bv_with_name(Name::from("sandbox_memory"), bits);
+    }
+
+    let stored = state.read(addr, bits)?;
+    if !track_double_fetches {
+        return Ok(stored);
+    }
+    let site = Site::current(state);
+    let earlier = with_context(|ctx| {
+        ctx.sandbox_fetches
+            .visible(state)
+            .filter(|fetch| fetch.stored.get_width() == bits)
+            .filter(|fetch| state.bvs_must_be_equal(&fetch.addr, addr).unwrap())
+            .filter(|fetch| state.bvs_must_be_equal(&fetch.stored, &stored).unwrap())
+            .last()
+            .cloned()
+    });
+    let value = match earlier {
+        Some(first) => {
+            let refetched = state.new_bv_with_name(Name::from("refetched_sandbox_memory"), bits)?;
+            let double_fetch = DoubleFetch {
+                first: first.site,
+                second: site.clone(),
+                first_value: stored.clone(),
+                second_value: refetched.clone(),
+            };
+            record_double_fetch(state, double_fetch);
+            refetched
+        }
+        None => stored.clone(),
+    };
+    let fetch = SandboxFetch {
+        addr: addr.clone(),
+        stored,
+        site,
+    };
+    with_context(|ctx| ctx.sandbox_fetches.record(state, fetch));
+    Ok(value)
+}
@@ -0,0 +1,357 @@
+use crate::allocations::to_pointer_width;
+use crate::context::with_context;
+use crate::location::callstack;
+use crate::sandbox::in_sandbox_memory;
+use crate::utils::{get_function_name, operands};
+use haybale::backend::Backend;
+use haybale::backend::DefaultBackend;
+use haybale::config::Demangling;
+use haybale::{BBInstrIndex, ExecutionManager, Location, State};
+use llvm_ir::instruction::Call;
+use llvm_ir::terminator::Ret;
+use llvm_ir::{Instruction, Name, Operand, Terminator, Type};
+
+type BV = <DefaultBackend as Backend>::BV;
+
+// A value is tainted if the sandbox can influence it. Taint starts at the hooks that hand
+// sandbox values to the host (`UNSAFE_unverified`, `invoke_sandbox_function`) and at raw loads
+// of sandbox memory, and follows data flow from there: through every instruction's operands,
+// into and out of calls, and through memory, by remembering which bytes tainted values were
+// stored to. Control flow (branching on a tainted value) does not taint anything. The
+// `copy_and_verify*` validators are where taint ends: whatever they return has been through
+// the host's checks.
+
+/// Taint of one SSA value. Only the latest mark for a name counts, so a value redefined in a
+/// later loop iteration gets the taint of its new definition.
+#[derive(Debug, Clone)]
+pub struct ValueTaint {
+    pub func: String,
+    pub name: Name,
+    pub tainted: bool,
+    /// Set by the hook modelling the call that produced the value, which knows better than
+    /// generic propagation
+    pub definite: bool,
+}
+
+/// Whether the bytes `[addr, addr + bytes)` hold a tainted value, as of the store that wrote them
+#[derive(Debug, Clone)]
+pub struct MemoryTaint {
+    pub addr: BV,
+    pub bytes: BV,
+    pub tainted: bool,
+}
+
+/// Whether `operand` can be influenced by the sandbox, at the state's current location
+pub fn is_tainted(state: &State<DefaultBackend>, operand: &Operand) -> bool {
+    match operand {
+        Operand::LocalOperand { name, .. } => name_tainted(state, &state.cur_loc.func.name, name),
+        // Constants are the host's own
+        _ => false,
+    }
+}
+
+/// Whether the instruction the state is at, or is about to execute, consumes a tainted value
+pub fn instr_is_tainted(state: &State<DefaultBackend>, instr: &Instruction) -> bool {
+    operands(instr)
+        .into_iter()
+        .any(|operand| is_tainted(state, operand))
+}
+
+/// Whether the instruction the state is currently at consumes a tainted value
+pub fn current_instr_is_tainted(state: &State<DefaultBackend>) -> bool {
+    match state.cur_loc.instr {
+        BBInstrIndex::Instr(i) => instr_is_tainted(state, &state.cur_loc.bb.instrs[i]),
+        BBInstrIndex::Terminator => false,
+    }
+}
+
+fn latest_mark(state: &State<DefaultBackend>, func: &str, name: &Name) -> Option<ValueTaint> {
+    with_context(|ctx| {
+        ctx.value_taint
+            .visible(state)
+            .filter(|mark| mark.func == func && mark.name == *name)
+            .last()
+            .cloned()
+    })
+}
+
+fn name_tainted(state: &State<DefaultBackend>, func: &str, name: &Name) -> bool {
+    latest_mark(state, func, name).is_some_and(|mark| mark.tainted)
+}
+
+fn mark(state: &State<DefaultBackend>, func: &str, name: &Name, tainted: bool, definite: bool) {
+    // Untainted is the default, so there is nothing to record unless it overrides a mark
+    if !tainted && !definite && !name_tainted(state, func, name) {
+        return;
+    }
+    let mark = ValueTaint {
+        func: func.to_string(),
+        name: name.clone(),
+        tainted,
+        definite,
+    };
+    with_context(|ctx| ctx.value_taint.record(state, mark));
+}
+
+/// For hooks: set the taint of the result of the call being hooked, e.g. tainted for values
+/// handed over by the sandbox
+pub fn mark_call_result(state: &State<DefaultBackend>, tainted: bool) {
+    let BBInstrIndex::Instr(i) = state.cur_loc.instr else {
+        return;
+    };
+    if let Instruction::Call(Call {
+        dest: Some(dest), ..
+    }) = &state.cur_loc.bb.instrs[i]
+    {
+        mark(state, &state.cur_loc.func.name, dest, tainted, true);
+    }
+}
+
+/// For hooks: mark the `bytes` bytes at `addr` as holding a value handed over by the sandbox,
+/// e.g. a result returned through an sret pointer
+pub fn taint_memory(state: &State<DefaultBackend>, addr: &BV, bytes: u32) {
+    let bytes = state.bv_from_u32(bytes, addr.get_width());
+    record_store(state, addr.clone(), bytes, true);
+}
+
+/// Whether any of the bytes `[addr, addr + bytes)` had a tainted value stored into them
+pub fn stored_taint(state: &State<DefaultBackend>, addr: &BV, bytes: &BV) -> haybale::Result<bool> {
+    let records: Vec<MemoryTaint> =
+        with_context(|ctx| ctx.memory_taint.visible(state).cloned().collect());
+    let addr = to_pointer_width(addr);
+    let end = addr.add(&to_pointer_width(bytes));
+    // Newest store first: a tainted store that can overlap taints the read, an untainted one
+    // that certainly covers it means nothing older matters
+    for record in records.iter().rev() {
+        let record_end = record.addr.add(&record.bytes);
+        let overlaps = addr.ult(&record_end).and(&record.addr.ult(&end));
+        if !state.sat_with_extra_constraints(std::iter::once(&overlaps))? {
+            continue;
+        }
+        if record.tainted {
+            return Ok(true);
+        }
+        let covers = record.addr.ulte(&addr).and(&end.ulte(&record_end));
+        if !state.sat_with_extra_constraints(std::iter::once(&covers.not()))? {
+            return Ok(false);
+        }
+    }
+    Ok(false)
+}
+
+fn record_store(state: &State<DefaultBackend>, addr: BV, bytes: BV, tainted: bool) {
+    // Untainted stores only matter if they can overwrite something tainted
+    if !tainted && with_context(|ctx| ctx.memory_taint.visible(state).next().is_none()) {
+        return;
+    }
+    let record = MemoryTaint {
+        addr: to_pointer_width(&addr),
+        bytes: to_pointer_width(&bytes),
+        tainted,
+    };
+    with_context(|ctx| ctx.memory_taint.record(state, record));
+}
+
+fn type_bytes(state: &State<DefaultBackend>, ty: &Type) -> Option<u32> {
+    state.size_in_bits(ty).map(|bits| bits.div_ceil(8))
+}
+
+/// Instruction callback propagating taint, run before every instruction
+pub fn track_taint<'a>(
+    instr: &'a Instruction,
+    em: &ExecutionManager<'a, DefaultBackend>,
+) -> haybale::Result<()> {
+    let state = em.state();
+    resolve_returns(state);
+    let func = &state.cur_loc.func.name;
+    match instr {
+        Instruction::Load(load) => {
+            let addr = state.operand_to_bv(&load.address)?;
+            let bytes = type_bytes(state, &state.type_of(load)).unwrap_or(0);
+            let bytes_bv = state.bv_from_u32(bytes, addr.get_width());
+            // A raw read of sandbox memory is as tainted as going through an accessor
+            let from_sandbox = match in_sandbox_memory(state, &addr) {
+                Some(inside) => state.sat_with_extra_constraints(std::iter::once(&inside))?,
+                None => false,
+            };
+            let tainted = is_tainted(state, &load.address)
+                || from_sandbox
+                || stored_taint(state, &addr, &bytes_bv)?;
+            mark(state, func, &load.dest, tainted, false);
+        }
+        Instruction::Store(store) => {
+            let addr = state.operand_to_bv(&store.address)?;
+            let bytes = type_bytes(state, &state.type_of(&store.value)).unwrap_or(0);
+            let bytes_bv = state.bv_from_u32(bytes, addr.get_width());
+            record_store(state, addr, bytes_bv, is_tainted(state, &store.value));
+        }
+        Instruction::Call(call) => track_call(state, call)?,
+        Instruction::Phi(phi) => {
+            // Only the value coming in from the block we actually came from counts
+            let path = state.get_path();
+            let tainted = path
+                .len()
+                .checked_sub(2)
+                .map(|prev| &path[prev].0.bb.name)
+                .and_then(|prev| phi.incoming_values.iter().find(|(_, bb)| bb == prev))
+                .map_or_else(
+                    || instr_is_tainted(state, instr),
+                    |(value, _)| is_tainted(state, value),
+                );
+            mark(state, func, &phi.dest, tainted, false);
+        }
+        _ => {
+            if let Some(dest) = instr.try_get_result() {
+                mark(state, func, dest, instr_is_tainted(state, instr), false);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn track_call(state: &State<DefaultBackend>, call: &Call) -> haybale::Result<()> {
+    let callee = get_function_name(call);
+    let args = &call.arguments;
+    if let Some(name) = callee {
+        // memcpy and friends move taint along with the bytes
+        if name.starts_with("llvm.memcpy") || name.starts_with("llvm.memmove") {
+            let dest = state.operand_to_bv(&args[0].0)?;
+            let src = state.operand_to_bv(&args[1].0)?;
+            let len = state.operand_to_bv(&args[2].0)?;
+            let from_sandbox = match in_sandbox_memory(state, &src) {
+                Some(inside) => state.sat_with_extra_constraints(std::iter::once(&inside))?,
+                None => false,
+            };
+            let tainted = from_sandbox || stored_taint(state, &src, &len)?;
+            record_store(state, dest, len, tainted);
+            return Ok(());
+        }
+        // Parameters of a function we step into carry the taint of their arguments
+        if let Some((callee_def, _)) = state.proj.get_func_by_name(name) {
+            for (param, (arg, _)) in callee_def.parameters.iter().zip(args) {
+                mark(state, name, &param.name, is_tainted(state, arg), false);
+            }
+        }
+    }
+    let Some(dest) = &call.dest else {
+        return Ok(());
+    };
+    // Clear out whatever a previous execution of this call left, so that we can tell
+    // whether a hook marks the result this time around
+    let func = &state.cur_loc.func.name;
+    if latest_mark(state, func, dest).is_some_and(|mark| mark.tainted || mark.definite) {
+        let reset = ValueTaint {
+            func: func.clone(),
+            name: dest.clone(),
+            tainted: false,
+            definite: false,
+        };
+        with_context(|ctx| ctx.value_taint.record(state, reset));
+    }
+    if callee.is_some_and(|callee| is_validator(state, callee)) {
+        mark(state, func, dest, false, true);
+        return Ok(());
+    }
+    let tainted = handed_taint(state, call)?;
+    mark(state, func, dest, tainted, false);
+    Ok(())
+}
+
+// Whether a call was handed anything tainted, directly or behind a pointer
+fn handed_taint(state: &State<DefaultBackend>, call: &Call) -> haybale::Result<bool> {
+    for (arg, _) in &call.arguments {
+        if is_tainted(state, arg) {
+            return Ok(true);
+        }
+        if let Type::PointerType { pointee_type, .. } = state.type_of(arg).as_ref() {
+            if let Some(bytes) = type_bytes(state, pointee_type) {
+                let addr = state.operand_to_bv(arg)?;
+                let bytes = state.bv_from_u32(bytes, addr.get_width());
+                if stored_taint(state, &addr, &bytes)? {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Whether `func` is one of RLBox's validators, which hand a tainted value to a verifier and
+/// return what the verifier made of it
+pub fn is_validator(state: &State<DefaultBackend>, func: &str) -> bool {
+    is_demangled_validator(&Demangling::autodetect(state.proj).maybe_demangle(func))
+}
+
+fn is_demangled_validator(demangled: &str) -> bool {
+    demangled.contains("::copy_and_verify")
+}
+
+/// Whether the state is somewhere inside a validator, including the verifier it calls
+pub fn in_validator(state: &State<DefaultBackend>) -> bool {
+    callstack(state)
+        .iter()
+        .any(|frame| is_demangled_validator(&frame.func))
+}
+
+// Calls don't end basic blocks, so `track_call` settles a call's result as the call is made:
+// untainted if the callee is a validator, and otherwise tainted if it was handed anything
+// tainted, which is all we can tell about a callee that is opaque to us (hooked, or only
+// declared). A hook modelling the call can then mark the result itself.
+//
+// If we stepped into the callee, what it returned decides instead, once it has returned.
+// Returning pushes a path entry that starts right after the call. When the call was the last
+// instruction in its block, that entry starts at the terminator and no instruction callback
+// ever runs in it, so the first instruction of each entry also settles the returns made in
+// the terminator-only entries leading up to it, e.g. `%r = call @f(); ret %r` one level down.
+fn resolve_returns(state: &State<DefaultBackend>) {
+    let path = state.get_path();
+    if path.is_empty() || entry_start(&state.cur_loc) != entry_start(&path[path.len() - 1].0) {
+        return;
+    }
+    let mut first = path.len() - 1;
+    while first > 1 && matches!(path[first - 1].0.instr, BBInstrIndex::Terminator) {
+        first -= 1;
+    }
+    // Oldest first, as a return can pass on the result of the one before it
+    for returned_to in first.max(1)..path.len() {
+        resolve_return(state, &path[returned_to - 1].0, &path[returned_to].0);
+    }
+}
+
+// If `entry` is where a path returned to after a call, and `callee` the block that returned,
+// taint the call's result like the value returned
+fn resolve_return(state: &State<DefaultBackend>, callee: &Location<'_>, entry: &Location<'_>) {
+    let Some(Instruction::Call(call)) = entry_start(entry)
+        .checked_sub(1)
+        .map(|prev| &entry.bb.instrs[prev])
+    else {
+        return;
+    };
+    let Some(dest) = &call.dest else {
+        return;
+    };
+    if Some(callee.func.name.as_str()) != get_function_name(call) {
+        return;
+    }
+    let func = &entry.func.name;
+    if latest_mark(state, func, dest).is_some_and(|mark| mark.definite) {
+        return;
+    }
+    let tainted = match &callee.bb.term {
+        Terminator::Ret(Ret {
+            return_operand: Some(Operand::LocalOperand { name, .. }),
+            ..
+        }) => name_tainted(state, &callee.func.name, name),
+        _ => false,
+    };
+    mark(state, func, dest, tainted, false);
+}
+
+// Index of the first instruction a path entry (or location) covers, the terminator counting
+// as one past the last instruction
+fn entry_start(loc: &Location<'_>) -> usize {
+    match loc.instr {
+        BBInstrIndex::Instr(i) => i,
+        BBInstrIndex::Terminator => loc.bb.instrs.len(),
+    }
+}
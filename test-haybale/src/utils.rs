@@ -1,4 +1,5 @@
 use either::Either;
+use haybale::BBInstrIndex;
 use haybale::Error;
 use haybale::State;
 use haybale::backend::Backend;
@@ -6,7 +7,7 @@ use haybale::backend::DefaultBackend;
 use haybale::function_hooks::IsCall;
 use llvm_ir::Type;
 use llvm_ir::TypeRef;
-use llvm_ir::{Constant, Name, Operand};
+use llvm_ir::{Constant, Instruction, Name, Operand};
 
 // In what scenario is addr_space not 0?
 pub fn get_pointer_type(ty: &Type) -> TypeRef {
@@ -63,3 +64,115 @@ pub fn get_function_name(call: &dyn IsCall) -> Option<&str> {
         _ => None, // inline assembly
     }
 }
+
+/// Render the instruction (or terminator) the state is currently at
+pub fn current_instr(state: &State<DefaultBackend>) -> String {
+    let loc = &state.cur_loc;
+    match loc.instr {
+        BBInstrIndex::Instr(i) => loc.bb.instrs[i].to_string(),
+        BBInstrIndex::Terminator => loc.bb.term.to_string(),
+    }
+}
+
+/// The operands an instruction reads, for analyses that don't care which is which
+pub fn operands(instr: &Instruction) -> Vec<&Operand> {
+    use llvm_ir::instruction::*;
+    match instr {
+        Instruction::Add(Add {
+            operand0, operand1, ..
+        })
+        | Instruction::Sub(Sub {
+            operand0, operand1, ..
+        })
+        | Instruction::Mul(Mul {
+            operand0, operand1, ..
+        })
+        | Instruction::UDiv(UDiv {
+            operand0, operand1, ..
+        })
+        | Instruction::SDiv(SDiv {
+            operand0, operand1, ..
+        })
+        | Instruction::URem(URem {
+            operand0, operand1, ..
+        })
+        | Instruction::SRem(SRem {
+            operand0, operand1, ..
+        })
+        | Instruction::And(And {
+            operand0, operand1, ..
+        })
+        | Instruction::Or(Or {
+            operand0, operand1, ..
+        })
+        | Instruction::Xor(Xor {
+            operand0, operand1, ..
+        })
+        | Instruction::Shl(Shl {
+            operand0, operand1, ..
+        })
+        | Instruction::LShr(LShr {
+            operand0, operand1, ..
+        })
+        | Instruction::AShr(AShr {
+            operand0, operand1, ..
+        })
+        | Instruction::FAdd(FAdd {
+            operand0, operand1, ..
+        })
+        | Instruction::FSub(FSub {
+            operand0, operand1, ..
+        })
+        | Instruction::FMul(FMul {
+            operand0, operand1, ..
+        })
+        | Instruction::FDiv(FDiv {
+            operand0, operand1, ..
+        })
+        | Instruction::FRem(FRem {
+            operand0, operand1, ..
+        })
+        | Instruction::ICmp(ICmp {
+            operand0, operand1, ..
+        })
+        | Instruction::FCmp(FCmp {
+            operand0, operand1, ..
+        }) => vec![operand0, operand1],
+        Instruction::FNeg(FNeg { operand, .. })
+        | Instruction::Trunc(Trunc { operand, .. })
+        | Instruction::ZExt(ZExt { operand, .. })
+        | Instruction::SExt(SExt { operand, .. })
+        | Instruction::FPTrunc(FPTrunc { operand, .. })
+        | Instruction::FPExt(FPExt { operand, .. })
+        | Instruction::FPToUI(FPToUI { operand, .. })
+        | Instruction::FPToSI(FPToSI { operand, .. })
+        | Instruction::UIToFP(UIToFP { operand, .. })
+        | Instruction::SIToFP(SIToFP { operand, .. })
+        | Instruction::PtrToInt(PtrToInt { operand, .. })
+        | Instruction::IntToPtr(IntToPtr { operand, .. })
+        | Instruction::BitCast(BitCast { operand, .. })
+        | Instruction::AddrSpaceCast(AddrSpaceCast { operand, .. })
+        | Instruction::Freeze(Freeze { operand, .. }) => vec![operand],
+        Instruction::ExtractElement(i) => vec![&i.vector, &i.index],
+        Instruction::InsertElement(i) => vec![&i.vector, &i.element, &i.index],
+        Instruction::ShuffleVector(i) => vec![&i.operand0, &i.operand1],
+        Instruction::ExtractValue(i) => vec![&i.aggregate],
+        Instruction::InsertValue(i) => vec![&i.aggregate, &i.element],
+        Instruction::Alloca(i) => vec![&i.num_elements],
+        Instruction::Load(i) => vec![&i.address],
+        Instruction::Store(i) => vec![&i.address, &i.value],
+        Instruction::CmpXchg(i) => vec![&i.address, &i.expected, &i.replacement],
+        Instruction::AtomicRMW(i) => vec![&i.address, &i.value],
+        Instruction::GetElementPtr(i) => std::iter::once(&i.address).chain(&i.indices).collect(),
+        Instruction::Phi(i) => i.incoming_values.iter().map(|(value, _)| value).collect(),
+        Instruction::Select(i) => vec![&i.condition, &i.true_value, &i.false_value],
+        Instruction::Call(i) => {
+            let callee = i.function.as_ref().right().into_iter();
+            callee
+                .chain(i.arguments.iter().map(|(arg, _)| arg))
+                .collect()
+        }
+        Instruction::VAArg(i) => vec![&i.arg_list],
+        _ => Vec::new(),
+    }
+}
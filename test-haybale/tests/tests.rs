@@ -1,10 +1,20 @@
 use haybale::Project;
+use haybale::backend::BV;
+use haybale::config::Demangling;
 use std::path::Path;
 use std::sync::Once;
-use test_haybale::exec::symex_and_check;
-
-// TODO: currently these tests just make sure that negative tests get an error, and positive tests get an ok
-// we should probably check that they're actually getting the right errors
+use test_haybale::checkers::double_fetch::DoubleFetchChecker;
+use test_haybale::checkers::oob::OobChecker;
+use test_haybale::checkers::{CheckErr, Checker, ExecutionTrace, Severity, TraceResult, Violation};
+use test_haybale::context::with_context;
+use test_haybale::exec::{
+    ExecOptions, PathStatus, symex_and_check, symex_and_check_paths, symex_and_check_with,
+    symex_func_and_monitor,
+};
+use test_haybale::report::check_function;
+use test_haybale::report::json::to_json;
+use test_haybale::report::sarif::to_sarif;
+use test_haybale::sandbox::SandboxMemoryModel;
 
 static INIT: Once = Once::new();
 
@@ -15,8 +25,9 @@ fn setup_logger() {
     });
 }
 
-/// Helper to run symex_and_check and assert the result is as expected
-fn run_and_assert_err(func_name: &str, expect_err: bool) {
+/// Helper to run symex_and_check and assert that every path reports `expected`
+/// (or no violation at all, if `expected` is `None`)
+fn run_and_assert_err(func_name: &str, expected: Option<CheckErr>) {
     setup_logger();
     let binary_path = Path::new("../examples/host.bc");
     let project = Project::from_bc_path(binary_path).unwrap();
@@ -24,90 +35,1064 @@ fn run_and_assert_err(func_name: &str, expect_err: bool) {
     let results = symex_and_check(func_name, &project, loop_bound);
     //assert_eq!(results.len(), 1, "Expected exactly one result"); // TODO: need to expand to handle any number of paths
     for result in results {
-        if expect_err {
-            assert!(result.is_err(), "Expected Err, got: {:?}", result);
-        } else {
-            assert!(result.is_ok(), "Expected Ok, got: {:?}", result);
+        match expected {
+            Some(kind) => match &result {
+                Err(violations) => assert!(
+                    violations.iter().all(|violation| violation.kind == kind),
+                    "Expected {}, got: {:?}",
+                    kind,
+                    result
+                ),
+                Ok(()) => panic!("Expected {}, got: {:?}", kind, result),
+            },
+            None => assert!(result.is_ok(), "Expected Ok, got: {:?}", result),
+        }
+    }
+}
+
+/// Helper for functions with both safe and unsafe paths: asserts that some path reports `expected`
+/// and that no path reports anything else
+fn run_and_assert_some_err(func_name: &str, expected: CheckErr) {
+    setup_logger();
+    let binary_path = Path::new("../examples/host.bc");
+    let project = Project::from_bc_path(binary_path).unwrap();
+    let loop_bound = 1000;
+    let results = symex_and_check(func_name, &project, loop_bound);
+    for result in &results {
+        if let Err(violations) = result {
+            assert!(
+                violations
+                    .iter()
+                    .all(|violation| violation.kind == expected),
+                "Expected {}, got: {:?}",
+                expected,
+                result
+            );
         }
     }
+    assert!(
+        results.iter().any(|result| result.is_err()),
+        "Expected {} on some path, got: {:?}",
+        expected,
+        results
+    );
 }
 
 #[test]
 fn test_sandbox_array_index_unchecked_unsafe() {
-    run_and_assert_err("sandbox_array_index_unchecked_unsafe", true);
+    run_and_assert_err("sandbox_array_index_unchecked_unsafe", Some(CheckErr::Oob));
 }
 
 #[test]
 fn test_sandbox_array_index_unchecked_safe() {
-    run_and_assert_err("sandbox_array_index_unchecked_safe", false);
+    run_and_assert_err("sandbox_array_index_unchecked_safe", None);
 }
 
 #[test]
 fn test_sandbox_array_index_checked() {
-    run_and_assert_err("sandbox_array_index_checked", false);
+    run_and_assert_err("sandbox_array_index_checked", None);
+}
+
+#[test]
+fn test_sandbox_array_index_checked_signed() {
+    run_and_assert_some_err("sandbox_array_index_checked_signed", CheckErr::Oob);
+    // Only a negative index gets past the verifier
+    let violation = first_violation("sandbox_array_index_checked_signed");
+    let counterexample = violation.counterexample.as_ref().unwrap();
+    let (_, index) = counterexample
+        .sandbox_reads
+        .iter()
+        .find(|(label, _)| label.starts_with("get_raw_value"))
+        .unwrap_or_else(|| panic!("Got: {}", counterexample));
+    assert!(*index as u32 >= 0x8000_0000, "Got: {}", counterexample);
 }
 
 #[test]
 fn test_basic_oob_read() {
-    run_and_assert_err("basic_oob_read", true);
+    run_and_assert_err("basic_oob_read", Some(CheckErr::Oob));
 }
 
 #[test]
 fn test_basic_oob_write() {
-    run_and_assert_err("basic_oob_write", true);
+    run_and_assert_err("basic_oob_write", Some(CheckErr::Oob));
 }
 
 #[test]
 fn test_basic_oob_read_from_arg() {
-    run_and_assert_err("basic_oob_read_from_arg", true);
+    run_and_assert_err("basic_oob_read_from_arg", Some(CheckErr::Oob));
 }
 
 #[test]
 fn test_trivial_array_read() {
-    run_and_assert_err("trivial_array_read", false);
+    run_and_assert_err("trivial_array_read", None);
 }
 
 #[test]
 fn test_trivial_array_read_2d() {
-    run_and_assert_err("trivial_array_read_2d", false);
+    run_and_assert_err("trivial_array_read_2d", None);
 }
 
 #[test]
 fn test_trivial_struct_read() {
-    run_and_assert_err("trivial_struct_read", false);
+    run_and_assert_err("trivial_struct_read", None);
 }
 
 #[test]
 fn test_trivial_struct_read_nested() {
-    run_and_assert_err("trivial_struct_read_nested", false);
+    run_and_assert_err("trivial_struct_read_nested", None);
 }
 
 #[test]
 fn test_basic_null_read() {
-    run_and_assert_err("basic_null_read", true);
+    run_and_assert_err("basic_null_read", Some(CheckErr::DereferencedNull));
 }
 
 #[test]
 fn test_basic_null_write() {
-    run_and_assert_err("basic_null_write", true);
+    run_and_assert_err("basic_null_write", Some(CheckErr::DereferencedNull));
 }
 
 #[test]
 fn test_basic_null_write2() {
-    run_and_assert_err("basic_null_write2", true);
+    run_and_assert_err("basic_null_write2", Some(CheckErr::DereferencedNull));
 }
 
 #[test]
 fn test_basic_div_by_zero() {
-    run_and_assert_err("basic_div_by_zero", true);
+    run_and_assert_err("basic_div_by_zero", Some(CheckErr::DividedByZero));
 }
 
 #[test]
 fn test_basic_div_by_zero2() {
-    run_and_assert_err("basic_div_by_zero2", true);
+    run_and_assert_err("basic_div_by_zero2", Some(CheckErr::DividedByZero));
 }
 
 #[test]
 fn test_basic_div_by_zero_guarded() {
-    run_and_assert_err("basic_div_by_zero_guarded", false);
+    run_and_assert_err("basic_div_by_zero_guarded", None);
+}
+
+#[test]
+fn test_div_by_zero_after_guarded() {
+    run_and_assert_some_err("div_by_zero_after_guarded", CheckErr::DividedByZero);
+}
+
+#[test]
+fn test_two_independent_bugs_exhaustive() {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let options = ExecOptions {
+        exhaustive: true,
+        ..ExecOptions::default()
+    };
+    let results = symex_and_check_with("two_independent_bugs", &project, &options);
+    let kinds: Vec<CheckErr> = results
+        .iter()
+        .filter_map(|result| result.as_ref().err())
+        .flatten()
+        .map(|violation| violation.kind)
+        .collect();
+    assert!(kinds.contains(&CheckErr::Oob), "Got: {:?}", results);
+    assert!(
+        kinds.contains(&CheckErr::DividedByZero),
+        "Got: {:?}",
+        results
+    );
+}
+
+#[test]
+fn test_basic_oob_read_from_arg_counterexample() {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let results =
+        symex_and_check_with("basic_oob_read_from_arg", &project, &ExecOptions::default());
+    let violation = results
+        .iter()
+        .filter_map(|result| result.as_ref().err())
+        .flatten()
+        .next()
+        .expect("expected a violation");
+    let counterexample = violation
+        .counterexample
+        .as_ref()
+        .expect("expected a counterexample");
+    let (_, index) = counterexample
+        .params
+        .iter()
+        .find(|(name, _)| name.ends_with("index"))
+        .expect("expected a value for the index parameter");
+    assert!(*index >= 4, "Got: {}", counterexample);
+}
+
+#[test]
+fn test_sandbox_dependent_reads_counterexample() {
+    let violation = first_violation("sandbox_dependent_reads");
+    assert_eq!(violation.kind, CheckErr::Oob);
+    // Both reads come from the one model, so they agree with the branch taken
+    let counterexample = violation.counterexample.as_ref().unwrap();
+    let reads: Vec<u32> = counterexample
+        .sandbox_reads
+        .iter()
+        .filter(|(label, _)| label.starts_with("UNSAFE_unverified"))
+        .map(|(_, value)| *value as u32)
+        .collect();
+    assert_eq!(reads.len(), 2, "Got: {}", counterexample);
+    assert_eq!(
+        reads[0],
+        reads[1].wrapping_add(1),
+        "Got: {}",
+        counterexample
+    );
+}
+
+#[test]
+fn test_basic_div_by_zero_location() {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let results = symex_and_check_with("basic_div_by_zero", &project, &ExecOptions::default());
+    let violation = results
+        .iter()
+        .filter_map(|result| result.as_ref().err())
+        .flatten()
+        .next()
+        .expect("expected a violation");
+    let loc = violation.loc.as_ref().expect("expected a source location");
+    assert!(loc.file.ends_with("host.cpp"), "Got: {}", loc);
+    let source = std::fs::read_to_string("../examples/host.cpp").unwrap();
+    let expected_line = source
+        .lines()
+        .position(|line| line.contains("return 3 / 0;"))
+        .unwrap()
+        + 1;
+    assert_eq!(loc.line as usize, expected_line, "Got: {}", loc);
+    assert!(
+        violation.demangled_func.starts_with("basic_div_by_zero"),
+        "Got: {}",
+        violation.demangled_func
+    );
+}
+
+#[test]
+fn test_disabled_checker_reports_nothing() {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let mut options = ExecOptions::default();
+    assert!(options.checkers.set_enabled("div_by_zero", false));
+    let results = symex_and_check_with("basic_div_by_zero", &project, &options);
+    for result in results {
+        assert!(result.is_ok(), "Expected Ok, got: {:?}", result);
+    }
+}
+
+/// Project-specific checker, registered from outside the crate, that flags every completed path
+struct EveryPathChecker;
+
+impl Checker for EveryPathChecker {
+    fn name(&self) -> &'static str {
+        "every_path"
+    }
+
+    fn description(&self) -> &'static str {
+        "Flags every path that returns"
+    }
+
+    fn check_path(&self, trace: &ExecutionTrace<'_>) -> Vec<Violation> {
+        match trace.result {
+            Ok(_) => vec![Violation::new(
+                CheckErr::Custom("EveryPath"),
+                self.name(),
+                &trace.state.cur_loc.func.name,
+                "ret",
+            )],
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+#[test]
+fn test_custom_checker() {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let mut options = ExecOptions::default();
+    options.checkers.register(EveryPathChecker);
+    let results = symex_and_check_with("trivial_array_read", &project, &options);
+    for result in results {
+        let violations = result.expect_err("expected the custom checker to fire");
+        assert!(
+            violations
+                .iter()
+                .all(|violation| violation.kind == CheckErr::Custom("EveryPath")),
+            "Got: {:?}",
+            violations
+        );
+    }
+}
+
+/// Flags every path haybale gave up on, on top of haybale's own error
+struct FailedPathChecker;
+
+impl Checker for FailedPathChecker {
+    fn name(&self) -> &'static str {
+        "failed_path"
+    }
+
+    fn description(&self) -> &'static str {
+        "Flags every path haybale could not execute"
+    }
+
+    fn check_path(&self, trace: &ExecutionTrace<'_>) -> Vec<Violation> {
+        match trace.result {
+            Ok(_) => Vec::new(),
+            Err(_) => vec![Violation::new(
+                CheckErr::Custom("FailedPath"),
+                self.name(),
+                &trace.state.cur_loc.func.name,
+                "call",
+            )],
+        }
+    }
+}
+
+#[test]
+fn test_haybale_error_kept_beside_violations() {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let mut options = ExecOptions::default();
+    options.checkers.register(FailedPathChecker);
+    let paths = symex_and_check_paths("calls_unmodelled_function", &project, &options);
+    assert!(!paths.is_empty());
+    for path in paths {
+        assert!(
+            matches!(&path.status, PathStatus::Error(err) if err.contains("getpid")),
+            "Got: {:?}",
+            path.status
+        );
+        let violations = path
+            .result
+            .expect_err("expected the checker's violation to be kept");
+        assert_eq!(violations[0].kind, CheckErr::Custom("FailedPath"));
+    }
+}
+
+/// Run only the allocation-aware bounds checker, so GEP-level findings don't mask its own
+fn run_mem_safety(func_name: &str) -> Vec<TraceResult> {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let mut options = ExecOptions::default();
+    options.checkers.set_enabled("oob", false);
+    symex_and_check_with(func_name, &project, &options)
+}
+
+#[test]
+fn test_heap_read_in_bounds() {
+    for result in run_mem_safety("heap_read_in_bounds") {
+        assert!(result.is_ok(), "Expected Ok, got: {:?}", result);
+    }
+}
+
+#[test]
+fn test_heap_oob_write() {
+    for result in run_mem_safety("heap_oob_write") {
+        let violations = result.expect_err("expected an out-of-bounds write");
+        assert!(
+            violations
+                .iter()
+                .any(|violation| violation.kind == CheckErr::Oob
+                    && violation.checker == "mem_safety"),
+            "Got: {:?}",
+            violations
+        );
+    }
+}
+
+#[test]
+fn test_memcpy_oob() {
+    let results = run_mem_safety("memcpy_oob");
+    assert!(
+        results.iter().any(|result| matches!(result, Err(violations)
+            if violations.iter().any(|violation| violation.checker == "mem_safety"))),
+        "Got: {:?}",
+        results
+    );
+}
+
+#[test]
+fn test_param_pointer_unknown_provenance() {
+    for result in run_mem_safety("param_pointer_read") {
+        if let Err(violations) = &result {
+            assert!(
+                violations
+                    .iter()
+                    .all(|violation| violation.checker != "mem_safety"),
+                "Got: {:?}",
+                violations
+            );
+        }
+    }
+}
+
+/// Run with per-dimension and struct field bounds checking
+fn run_strict_bounds(func_name: &str) -> Vec<TraceResult> {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let mut options = ExecOptions::default();
+    options.checkers.register(OobChecker::strict());
+    symex_and_check_with(func_name, &project, &options)
+}
+
+#[test]
+fn test_strict_bounds_safe() {
+    for func_name in [
+        "trivial_array_read_2d",
+        "trivial_struct_read_nested",
+        "struct_read_two_fields",
+        "struct_field_array_read",
+        "struct_field_array_end",
+    ] {
+        for result in run_strict_bounds(func_name) {
+            assert!(
+                result.is_ok(),
+                "{}: expected Ok, got: {:?}",
+                func_name,
+                result
+            );
+        }
+    }
+}
+
+#[test]
+fn test_strict_bounds_inner_dimension() {
+    for result in run_strict_bounds("array_read_2d_inner_oob") {
+        let violations = result.expect_err("expected the column index to be out of bounds");
+        assert!(
+            violations
+                .iter()
+                .all(|violation| violation.kind == CheckErr::Oob),
+            "Got: {:?}",
+            violations
+        );
+    }
+}
+
+#[test]
+fn test_strict_bounds_symbolic_dimension() {
+    let results = run_strict_bounds("array_read_2d_from_arg");
+    assert!(
+        results.iter().any(|result| result.is_err()),
+        "Got: {:?}",
+        results
+    );
+}
+
+#[test]
+fn test_strict_bounds_struct_field_overflow() {
+    let results = run_strict_bounds("struct_field_array_overflow");
+    let violation = results
+        .iter()
+        .filter_map(|result| result.as_ref().err())
+        .flatten()
+        .next()
+        .expect("expected the field overflow to be reported");
+    assert_eq!(violation.kind, CheckErr::Oob);
+    assert_eq!(violation.checker, "oob");
+}
+
+#[test]
+fn test_json_report() {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let report = check_function("basic_div_by_zero", &project, &ExecOptions::default());
+    let json: serde_json::Value = serde_json::from_str(&to_json("host.bc", &[report])).unwrap();
+    assert_eq!(json["schema_version"], 1);
+    let function = &json["functions"][0];
+    assert_eq!(function["status"], "analyzed");
+    let path = &function["paths"][0];
+    assert_eq!(path["status"], "violation");
+    let finding = &path["findings"][0];
+    assert_eq!(finding["checker"], "div_by_zero");
+    assert_eq!(finding["kind"], "DividedByZero");
+    assert!(finding["location"]["line"].is_u64(), "Got: {}", finding);
+}
+
+#[test]
+fn test_sarif_report() {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let options = ExecOptions::default();
+    let report = check_function("basic_div_by_zero", &project, &options);
+    let sarif: serde_json::Value =
+        serde_json::from_str(&to_sarif(&[report], &options.checkers)).unwrap();
+    assert_eq!(sarif["version"], "2.1.0");
+    let run = &sarif["runs"][0];
+    let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+    assert_eq!(rules.len(), options.checkers.all().count());
+    let result = &run["results"][0];
+    assert_eq!(result["ruleId"], "div_by_zero");
+    assert_eq!(
+        rules[result["ruleIndex"].as_u64().unwrap() as usize]["id"],
+        "div_by_zero"
+    );
+    let region = &result["locations"][0]["physicalLocation"]["region"];
+    assert!(region["startLine"].is_u64(), "Got: {}", result);
+    let steps = result["codeFlows"][0]["threadFlows"][0]["locations"]
+        .as_array()
+        .unwrap();
+    assert!(!steps.is_empty(), "Got: {}", result);
+}
+
+#[test]
+fn test_sandbox_index_after_invoke() {
+    run_and_assert_some_err("sandbox_index_after_invoke", CheckErr::Oob);
+}
+
+#[test]
+fn test_sandbox_return_as_index() {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let results =
+        symex_and_check_with("sandbox_return_as_index", &project, &ExecOptions::default());
+    let violation = results
+        .iter()
+        .filter_map(|result| result.as_ref().err())
+        .flatten()
+        .next()
+        .expect("expected the returned index to be out of bounds");
+    assert_eq!(violation.kind, CheckErr::Oob);
+    let counterexample = violation.counterexample.as_ref().unwrap();
+    assert!(
+        counterexample
+            .sandbox_reads
+            .iter()
+            .any(|(label, _)| label.starts_with("invoke_sandbox_function")),
+        "Got: {}",
+        counterexample
+    );
+}
+
+#[test]
+fn test_sandbox_index_rewritten_after_invoke() {
+    run_and_assert_err("sandbox_index_rewritten_after_invoke", None);
+}
+
+#[test]
+fn test_sandbox_index_read_twice_single_threaded() {
+    run_and_assert_err("sandbox_index_read_twice", None);
+}
+
+#[test]
+fn test_sandbox_index_read_twice_volatile() {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let options = ExecOptions {
+        sandbox_memory: SandboxMemoryModel::Volatile,
+        ..ExecOptions::default()
+    };
+    let results = symex_and_check_with("sandbox_index_read_twice", &project, &options);
+    assert!(
+        results.iter().any(|result| matches!(result, Err(violations)
+            if violations.iter().any(|violation| violation.kind == CheckErr::Oob))),
+        "Got: {:?}",
+        results
+    );
+}
+
+#[test]
+fn test_sandbox_raw_pointer_read_twice_volatile() {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    // Raw loads are not freshened, so the check on the first read still covers the second
+    let options = ExecOptions {
+        sandbox_memory: SandboxMemoryModel::Volatile,
+        ..ExecOptions::default()
+    };
+    for result in symex_and_check_with("sandbox_raw_pointer_read_twice", &project, &options) {
+        assert!(result.is_ok(), "Expected Ok, got: {:?}", result);
+    }
+}
+
+/// Asserts some path reports a double fetch, with both read sites
+fn assert_double_fetch(func_name: &str) {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let mut options = ExecOptions::default();
+    options.checkers.register(DoubleFetchChecker);
+    let results = symex_and_check_with(func_name, &project, &options);
+    let violation = results
+        .iter()
+        .filter_map(|result| result.as_ref().err())
+        .flatten()
+        .find(|violation| violation.kind == CheckErr::DoubleFetch)
+        .unwrap_or_else(|| panic!("expected a double fetch, got: {:?}", results));
+    let labels: Vec<&str> = violation
+        .related
+        .iter()
+        .map(|(label, _)| label.as_str())
+        .collect();
+    assert!(labels.contains(&"first read"), "Got: {:?}", labels);
+    assert!(labels.contains(&"second read"), "Got: {:?}", labels);
+    let lines: Vec<u32> = violation
+        .related
+        .iter()
+        .filter_map(|(_, site)| site.loc.as_ref().map(|loc| loc.line))
+        .collect();
+    assert!(lines.len() >= 2, "Got: {:?}", violation.related);
+}
+
+#[test]
+fn test_double_fetch_index() {
+    assert_double_fetch("sandbox_index_read_twice");
+}
+
+#[test]
+fn test_double_fetch_copy() {
+    assert_double_fetch("sandbox_double_fetch_copy");
+}
+
+/// The first violation any path reports
+fn first_violation(func_name: &str) -> Violation {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let results = symex_and_check_with(func_name, &project, &ExecOptions::default());
+    results
+        .iter()
+        .filter_map(|result| result.as_ref().err())
+        .flatten()
+        .next()
+        .cloned()
+        .unwrap_or_else(|| panic!("expected a violation, got: {:?}", results))
+}
+
+#[test]
+fn test_host_index_not_sandbox_controlled() {
+    let violation = first_violation("basic_oob_read");
+    assert!(!violation.sandbox_controlled, "Got: {}", violation);
+}
+
+#[test]
+fn test_sandbox_return_sandbox_controlled() {
+    let violation = first_violation("sandbox_return_as_index");
+    assert_eq!(violation.kind, CheckErr::Oob);
+    assert!(violation.sandbox_controlled, "Got: {}", violation);
+}
+
+#[test]
+fn test_taint_through_host_memory() {
+    let violation = first_violation("sandbox_index_through_host_memory");
+    assert_eq!(violation.kind, CheckErr::Oob);
+    assert!(violation.sandbox_controlled, "Got: {}", violation);
+}
+
+/// Every unchecked tainted use warned about, across all paths, as (kind of sink, line)
+fn tainted_use_warnings(func_name: &str) -> Vec<(String, Option<u32>)> {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let report = check_function(func_name, &project, &ExecOptions::default());
+    report
+        .warnings()
+        .filter(|warning| warning.kind == CheckErr::UncheckedTaintedUse)
+        .map(|warning| {
+            assert_eq!(warning.severity, Severity::Warning);
+            assert!(warning.sandbox_controlled, "Got: {}", warning);
+            (
+                warning.values[0].0.clone(),
+                warning.loc.as_ref().map(|loc| loc.line),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_tainted_use_unchecked_but_benign() {
+    // No error, since the index happens to be in bounds, but it is still unchecked
+    run_and_assert_err("sandbox_array_index_unchecked_safe", None);
+    let warnings = tainted_use_warnings("sandbox_array_index_unchecked_safe");
+    assert!(
+        warnings.iter().any(|(sink, _)| sink == "index"),
+        "Got: {:?}",
+        warnings
+    );
+}
+
+#[test]
+fn test_tainted_use_verified() {
+    let warnings = tainted_use_warnings("sandbox_array_index_checked");
+    assert!(warnings.is_empty(), "Got: {:?}", warnings);
+}
+
+#[test]
+fn test_tainted_use_in_validator() {
+    run_and_assert_err("sandbox_index_in_validator", None);
+    let warnings = tainted_use_warnings("sandbox_index_in_validator");
+    assert!(warnings.is_empty(), "Got: {:?}", warnings);
+}
+
+#[test]
+fn test_tainted_use_host_values() {
+    let warnings = tainted_use_warnings("basic_oob_read_from_arg");
+    assert!(warnings.is_empty(), "Got: {:?}", warnings);
+}
+
+#[test]
+fn test_tainted_use_returned_directly() {
+    run_and_assert_err("sandbox_index_returned_directly", None);
+    let warnings = tainted_use_warnings("sandbox_index_returned_directly");
+    assert!(
+        warnings.iter().any(|(sink, _)| sink == "index"),
+        "Got: {:?}",
+        warnings
+    );
+}
+
+#[test]
+fn test_tainted_loop_bound() {
+    run_and_assert_err("sandbox_loop_bound", None);
+    let warnings = tainted_use_warnings("sandbox_loop_bound");
+    assert!(
+        warnings.iter().any(|(sink, _)| sink == "loop bound"),
+        "Got: {:?}",
+        warnings
+    );
+}
+
+#[test]
+fn test_sandbox_string_checked() {
+    // RLBox indexes into the copy with pointer arithmetic, so only the allocation-aware checker
+    // can tell whether it stays in bounds
+    for result in run_mem_safety("sandbox_string_checked") {
+        assert!(result.is_ok(), "Expected Ok, got: {:?}", result);
+    }
+}
+
+#[test]
+fn test_sandbox_string_unbounded() {
+    let results = run_mem_safety("sandbox_string_unbounded");
+    assert!(
+        results.iter().any(|result| matches!(result, Err(violations)
+            if violations.iter().any(|violation| violation.kind == CheckErr::Oob))),
+        "Got: {:?}",
+        results
+    );
+}
+
+/// Lines of the `copy_and_verify_string` calls whose verifier is reported
+fn unverified_string_errors(func_name: &str) -> Vec<Option<u32>> {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let report = check_function(func_name, &project, &ExecOptions::default());
+    report
+        .violations()
+        .filter(|violation| violation.kind == CheckErr::UnverifiedString)
+        .map(|violation| {
+            assert_eq!(violation.related[0].0, "copied");
+            violation.related[0].1.loc.as_ref().map(|loc| loc.line)
+        })
+        .collect()
+}
+
+#[test]
+fn test_sandbox_string_verifier_bounded() {
+    let errors = unverified_string_errors("sandbox_string_checked");
+    assert!(errors.is_empty(), "Got: {:?}", errors);
+}
+
+#[test]
+fn test_sandbox_string_verifier_unbounded() {
+    let errors = unverified_string_errors("sandbox_string_unbounded");
+    assert!(!errors.is_empty());
+}
+
+#[test]
+fn test_sandbox_string_verifier_unterminated() {
+    let errors = unverified_string_errors("sandbox_string_unterminated");
+    assert!(!errors.is_empty());
+}
+
+#[test]
+fn test_sandbox_string_verifier_over_limit() {
+    let errors = unverified_string_errors("sandbox_string_over_limit");
+    assert!(!errors.is_empty());
+}
+
+#[test]
+fn test_sandbox_string_unverified() {
+    run_and_assert_err(
+        "sandbox_string_unverified",
+        Some(CheckErr::UnverifiedString),
+    );
+}
+
+#[test]
+fn test_hello_cb() {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let demangling = Demangling::autodetect(&project);
+    let (hello_cb, _) = project
+        .all_functions()
+        .find(|(func, _)| {
+            demangling
+                .maybe_demangle(&func.name)
+                .starts_with("hello_cb(")
+        })
+        .expect("hello_cb not found");
+    let mut options = ExecOptions::default();
+    options.checkers.set_enabled("oob", false);
+    let report = check_function(&hello_cb.name, &project, &options);
+    assert!(report.outcome.is_ok(), "Got: {:?}", report.outcome);
+    let violations: Vec<_> = report.violations().collect();
+    assert!(violations.is_empty(), "Got: {:?}", violations);
+}
+
+/// Asserts that some path has a mem_safety out-of-bounds access
+fn assert_mem_safety_oob(func_name: &str) {
+    let results = run_mem_safety(func_name);
+    assert!(
+        results.iter().any(|result| matches!(result, Err(violations)
+            if violations.iter().any(|violation| violation.kind == CheckErr::Oob
+                && violation.checker == "mem_safety"))),
+        "Got: {:?}",
+        results
+    );
+}
+
+#[test]
+fn test_sandbox_range_in_bounds() {
+    for result in run_mem_safety("sandbox_range_in_bounds") {
+        assert!(result.is_ok(), "Expected Ok, got: {:?}", result);
+    }
+}
+
+#[test]
+fn test_sandbox_range_overread() {
+    assert_mem_safety_oob("sandbox_range_overread");
+}
+
+#[test]
+fn test_sandbox_range_sandbox_count() {
+    for result in run_mem_safety("sandbox_range_sandbox_count") {
+        assert!(result.is_ok(), "Expected Ok, got: {:?}", result);
+    }
+}
+
+#[test]
+fn test_sandbox_range_sandbox_count_unverified() {
+    assert_mem_safety_oob("sandbox_range_sandbox_count_unverified");
+}
+
+#[test]
+fn test_sandbox_buffer_address_in_bounds() {
+    for result in run_mem_safety("sandbox_buffer_address_in_bounds") {
+        assert!(result.is_ok(), "Expected Ok, got: {:?}", result);
+    }
+}
+
+#[test]
+fn test_sandbox_buffer_address_overflow() {
+    assert_mem_safety_oob("sandbox_buffer_address_overflow");
+}
+
+#[test]
+fn test_sandbox_index_64bit() {
+    run_and_assert_err("sandbox_index_64bit", Some(CheckErr::Oob));
+}
+
+#[test]
+fn test_sandbox_array_by_value() {
+    run_and_assert_err("sandbox_array_by_value", Some(CheckErr::Oob));
+    // All four elements are read, not just the first 32 bits
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let traces =
+        symex_func_and_monitor("sandbox_array_by_value", &project, &ExecOptions::default());
+    let widths: Vec<u32> = traces
+        .iter()
+        .flat_map(|trace| {
+            with_context(|ctx| {
+                ctx.sandbox_reads
+                    .visible(&trace.state)
+                    .filter(|read| read.label.starts_with("UNSAFE_unverified"))
+                    .map(|read| read.value.get_width())
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    assert!(!widths.is_empty());
+    assert!(
+        widths.iter().all(|width| *width == 256),
+        "Got: {:?}",
+        widths
+    );
+}
+
+#[test]
+fn test_sandbox_bool_index() {
+    run_and_assert_err("sandbox_bool_index", None);
+}
+
+#[test]
+fn test_sandbox_pointer_within_grant() {
+    for result in run_mem_safety("sandbox_pointer_within_grant") {
+        assert!(result.is_ok(), "Expected Ok, got: {:?}", result);
+    }
+}
+
+#[test]
+fn test_sandbox_pointer_beyond_grant() {
+    assert_mem_safety_oob("sandbox_pointer_beyond_grant");
+}
+
+#[test]
+fn test_sandbox_pointer_escape() {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let options = ExecOptions {
+        exhaustive: true,
+        ..ExecOptions::default()
+    };
+    let results = symex_and_check_with("sandbox_pointer_escape", &project, &options);
+    assert!(
+        results
+            .iter()
+            .filter_map(|result| result.as_ref().err())
+            .flatten()
+            .any(|violation| violation.kind == CheckErr::SandboxEscape
+                && violation.sandbox_controlled),
+        "Got: {:?}",
+        results
+    );
+}
+
+#[test]
+fn test_sandbox_use_before_create() {
+    run_and_assert_err("sandbox_use_before_create", Some(CheckErr::UseBeforeCreate));
+}
+
+#[test]
+fn test_sandbox_use_after_destroy() {
+    let violation = first_violation("sandbox_use_after_destroy");
+    assert_eq!(violation.kind, CheckErr::UseAfterDestroy);
+    assert_eq!(violation.related[0].0, "sandbox destroyed");
+}
+
+#[test]
+fn test_sandbox_double_destroy() {
+    run_and_assert_err("sandbox_double_destroy", Some(CheckErr::DoubleDestroy));
+}
+
+#[test]
+fn test_sandbox_leak() {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let options = ExecOptions::default();
+    // Never destroys its sandbox
+    let report = check_function("sandbox_array_index_unchecked_safe", &project, &options);
+    let leaks: Vec<_> = report
+        .warnings()
+        .filter(|warning| warning.kind == CheckErr::SandboxLeak)
+        .collect();
+    assert!(!leaks.is_empty(), "Got: {:?}", report.outcome);
+    assert!(
+        leaks
+            .iter()
+            .all(|leak| leak.related[0].0 == "sandbox created")
+    );
+    // Destroys it
+    let report = check_function("sandbox_array_index_checked", &project, &options);
+    assert!(
+        report
+            .warnings()
+            .all(|warning| warning.kind != CheckErr::SandboxLeak),
+        "Got: {:?}",
+        report.outcome
+    );
+}
+
+#[test]
+fn test_sandbox_use_after_free() {
+    let violation = first_violation("sandbox_use_after_free");
+    assert_eq!(violation.kind, CheckErr::UseAfterFree);
+    let labels: Vec<&str> = violation
+        .related
+        .iter()
+        .map(|(label, _)| label.as_str())
+        .collect();
+    assert_eq!(labels, ["allocated", "freed"]);
+}
+
+#[test]
+fn test_sandbox_double_free() {
+    run_and_assert_err("sandbox_double_free", Some(CheckErr::DoubleFree));
+}
+
+#[test]
+fn test_sandbox_free_guest_pointer() {
+    run_and_assert_err("sandbox_free_guest_pointer", Some(CheckErr::InvalidFree));
+}
+
+#[test]
+fn test_sandbox_free_once() {
+    run_and_assert_err("sandbox_free_once", None);
+}
+
+/// Every sandbox memory leak warned about, across all paths, as the line of the allocation
+fn sandbox_memory_leaks(func_name: &str) -> Vec<Option<u32>> {
+    sandbox_memory_leaks_with(func_name, &ExecOptions::default())
+}
+
+fn sandbox_memory_leaks_with(func_name: &str, options: &ExecOptions) -> Vec<Option<u32>> {
+    setup_logger();
+    let project = Project::from_bc_path(Path::new("../examples/host.bc")).unwrap();
+    let report = check_function(func_name, &project, options);
+    report
+        .warnings()
+        .filter(|warning| warning.kind == CheckErr::SandboxMemoryLeak)
+        .map(|warning| {
+            assert_eq!(warning.related[0].0, "allocated");
+            warning.related[0].1.loc.as_ref().map(|loc| loc.line)
+        })
+        .collect()
+}
+
+#[test]
+fn test_sandbox_memory_leak() {
+    run_and_assert_err("sandbox_memory_leak", None);
+    // Only the array, which is allocated after the buffer
+    let leaks = sandbox_memory_leaks("sandbox_memory_leak");
+    assert_eq!(leaks.len(), 1, "Got: {:?}", leaks);
+}
+
+#[test]
+fn test_sandbox_memory_kept() {
+    let leaks = sandbox_memory_leaks("sandbox_memory_kept");
+    assert!(leaks.is_empty(), "Got: {:?}", leaks);
+}
+
+#[test]
+fn test_sandbox_memory_abort() {
+    let leaks = sandbox_memory_leaks("sandbox_memory_abort");
+    assert!(leaks.is_empty(), "Got: {:?}", leaks);
+}
+
+#[test]
+fn test_sandbox_memory_reclaimed_with_sandbox() {
+    // Never frees its array, but destroys the sandbox
+    let leaks = sandbox_memory_leaks("sandbox_array_index_checked");
+    assert!(leaks.is_empty(), "Got: {:?}", leaks);
+}
+
+#[test]
+fn test_sandbox_memory_leak_without_mem_safety() {
+    // Which stores escape depends on the stack allocations, which are tracked either way
+    let mut options = ExecOptions::default();
+    options.checkers.set_enabled("mem_safety", false);
+    let leaks = sandbox_memory_leaks_with("sandbox_memory_leak", &options);
+    assert_eq!(leaks, sandbox_memory_leaks("sandbox_memory_leak"));
+    let leaks = sandbox_memory_leaks_with("sandbox_memory_kept", &options);
+    assert!(leaks.is_empty(), "Got: {:?}", leaks);
+}
+
+#[test]
+fn test_sandbox_havoc_symbolic_size() {
+    // The block can be as large as the whole region, which is never materialized at once
+    let violation = first_violation("sandbox_havoc_symbolic_size");
+    assert_eq!(violation.kind, CheckErr::Oob);
+    assert!(violation.sandbox_controlled, "Got: {}", violation);
 }